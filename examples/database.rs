@@ -7,23 +7,23 @@ fn main() {
     let mut db = Database::new("./hello.dino");
 
     // Load and create the database if does not exist
-    db.load();
+    db.load().unwrap();
 
     // Insert values in the db in the format of key, value
-    db.insert("key", "q");
+    db.insert("key", "q").unwrap();
 
     // Create a new sub Tree in the main Tree of the db
     let mut data_tree = Tree::new();
 
     // Insert the key and value in the sub tree
-    data_tree.insert("b", "c");
+    data_tree.insert("b", "c").unwrap();
 
     // The length of items in the sub tree in the database
     // This also shows almost all of the functions in Database are also avaliable in Tree
     println!("The length of items in the sub tree in the database is: {}", data_tree.len());
 
     // Insert the [data_tree] under the main tree
-    db.insert_tree("id", data_tree);
+    db.insert_tree("id", data_tree).unwrap();
 
     // Print the value of id
     println!("The value of key: id is:\n{}", db.find("id").unwrap());
@@ -39,7 +39,7 @@ fn main() {
     }
 
     // Remove a key in the database with its value
-    db.remove("id");
+    db.remove("id").unwrap();
 
     // Now here it wont print that it exists as it does not we removed it ^^^^^
     if db.contains_key("id") {
@@ -49,11 +49,11 @@ fn main() {
     println!("The length of items in the database is: {}", db.len());
 
     // Insert a number in the database
-    db.insert_number("test", 1);
-    db.insert_array("test-array", vec!["hello!"]);
-    db.insert_bool("test-bool", true);
+    db.insert_number("test", 1).unwrap();
+    db.insert_array("test-array", vec!["hello!"]).unwrap();
+    db.insert_bool("test-bool", true).unwrap();
 
-    println!("{}", db.find("test").unwrap().to_number() + 1); // This will print 2
-    println!("{:?}", db.find("test-array").unwrap().to_vec()); // This will print ["hello!"]
-    println!("{:?}", db.find("test-bool").unwrap().to_bool()); // This will print true
+    println!("{}", db.find("test").unwrap().to_number().unwrap() + 1); // This will print 2
+    println!("{:?}", db.find("test-array").unwrap().to_vec().unwrap()); // This will print ["hello!"]
+    println!("{:?}", db.find("test-bool").unwrap().to_bool().unwrap()); // This will print true
 }
\ No newline at end of file
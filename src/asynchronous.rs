@@ -0,0 +1,609 @@
+//! An async api for using a [Database] inside of an async runtime like tokio
+//!
+//! The [Database] takes blocking locks and does blocking file io, which would stall the
+//! threads of the runtime. [AsyncDatabase] runs every operation on the blocking thread pool
+//! of tokio instead, and writers wait for each other on an async lock so a queue of writes
+//! does not tie up a blocking thread each. This is only there with the `async` feature.
+
+use std::ops::{ Bound, RangeBounds };
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::{ Duration, SystemTime };
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::Mutex;
+
+use crate::{ Database, DocId, Error, Event, Iter, Keys, Page, Query, Result, Schema, Table, Transaction, Tree, Updated, Value, Values, WriteBatch };
+
+/// A range of borrowed keys as owned bounds that can be moved to the blocking thread pool
+fn owned<'r, R: RangeBounds<&'r str>>(range: &R) -> (Bound<String>, Bound<String>) {
+    return (range.start_bound().map(|key| key.to_string()), range.end_bound().map(|key| key.to_string()));
+}
+
+/// Run `f` on the blocking thread pool. A panic in `f` goes on in the task that awaits it
+async fn blocking<T: Send + 'static, F: FnOnce() -> T + Send + 'static>(f: F) -> T {
+    return match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Err(error) => panic!("{}", error)
+    }
+}
+
+/// A [Database] with async methods that can be awaited inside of handlers of async web servers
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # tokio::runtime::Builder::new_multi_thread().build().unwrap().block_on(async {
+/// let db = AsyncDatabase::open(Database::in_memory()).await.unwrap();
+///
+/// db.insert("key", "value").await.unwrap();
+///
+/// assert_eq!(db.get::<String>("key").await.unwrap(), "value");
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    db: Arc<Database>,
+
+    /// Writers wait here without holding a thread of the runtime
+    writes: Arc<Mutex<()>>
+}
+
+impl AsyncDatabase {
+    /// Load `db` on the blocking thread pool. Set it up with the `enable_*` methods before
+    pub async fn open(mut db: Database) -> Result<AsyncDatabase> {
+        let db = blocking(move || db.load().map(|_| db)).await?;
+
+        return Ok(AsyncDatabase {
+            db: Arc::new(db),
+            writes: Arc::new(Mutex::new(()))
+        });
+    }
+
+    /// The [Database] itself, for [Database::on_change], [Database::start_sweeper] and the like.
+    /// Its methods block so do not call the ones that touch the data on the runtime
+    pub fn database(&self) -> &Arc<Database> {
+        return &self.db;
+    }
+
+    /// The default table
+    pub fn default_table(&self) -> AsyncTable {
+        return AsyncTable { db: self.clone(), name: None };
+    }
+
+    /// The table called `name`
+    pub fn table(&self, name: &str) -> AsyncTable {
+        return AsyncTable { db: self.clone(), name: Some(name.to_string()) };
+    }
+
+    /// Return the names of the named tables in the database sorted by name
+    pub async fn tables(&self) -> Vec<String> {
+        return self.read(|db| db.tables()).await;
+    }
+
+    /// Remove the table called `name` with everything in it
+    pub async fn drop_table(&self, name: &str) -> Result<()> {
+        let name = name.to_string();
+
+        return self.write(move |db| db.drop_table(&name)).await;
+    }
+
+    /// Rename the table called `from` to `to`
+    pub async fn rename_table(&self, from: &str, to: &str) -> Result<()> {
+        let (from, to) = (from.to_string(), to.to_string());
+
+        return self.write(move |db| db.rename_table(&from, &to)).await;
+    }
+
+    /// Run `f` as a transaction on the blocking thread pool. See [Database::transaction]
+    pub async fn transaction<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<Error> + Send + 'static,
+        F: FnOnce(&mut Transaction) -> std::result::Result<T, E> + Send + 'static
+    {
+        return self.write(move |db| db.transaction(f)).await;
+    }
+
+    /// Apply all of the changes in a [WriteBatch] in one go. See [Database::apply_batch]
+    pub async fn apply_batch(&self, batch: WriteBatch) -> Result<()> {
+        return self.write(move |db| db.apply_batch(batch)).await;
+    }
+
+    /// Save the changes that were made in manual flush mode
+    pub async fn flush(&self) -> Result<()> {
+        return self.write(|db| db.flush()).await;
+    }
+
+    /// Fold the journal back into the database file
+    pub async fn compact(&self) -> Result<()> {
+        return self.write(|db| db.compact()).await;
+    }
+
+    /// Remove every key of every table that has expired and return how many were removed
+    pub async fn purge_expired(&self) -> Result<usize> {
+        return self.write(|db| db.purge_expired()).await;
+    }
+
+    /// Insert a key and a value in the default table
+    pub async fn insert<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        return self.default_table().insert(key, value).await;
+    }
+
+    /// Insert a key and a value in the default table that expires after `ttl`
+    pub async fn insert_with_ttl<T: Serialize + ?Sized>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        return self.default_table().insert_with_ttl(key, value, ttl).await;
+    }
+
+    /// Remove a key in the default table with its value
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        return self.default_table().remove(key).await;
+    }
+
+    /// Set the value at a path like `user/address/city`
+    pub async fn set_path<T: Serialize + ?Sized>(&self, path: &str, value: &T) -> Result<()> {
+        return self.default_table().set_path(path, value).await;
+    }
+
+    /// Remove the value at a path
+    pub async fn remove_path(&self, path: &str) -> Result<()> {
+        return self.default_table().remove_path(path).await;
+    }
+
+    /// Get a value as any type that implements [DeserializeOwned]
+    pub async fn get<T: DeserializeOwned + Send + 'static>(&self, key: &str) -> Result<T> {
+        return self.default_table().get(key).await;
+    }
+
+    /// Find a value in the default table
+    pub async fn find(&self, key: &str) -> Result<Value> {
+        return self.default_table().find(key).await;
+    }
+
+    /// Get the value at a path as any type that implements [DeserializeOwned]
+    pub async fn get_path<T: DeserializeOwned + Send + 'static>(&self, path: &str) -> Result<T> {
+        return self.default_table().get_path(path).await;
+    }
+
+    /// Find the value at a path
+    pub async fn find_path(&self, path: &str) -> Result<Value> {
+        return self.default_table().find_path(path).await;
+    }
+
+    /// Check if a key exists in the default table
+    pub async fn contains_key(&self, key: &str) -> bool {
+        return self.default_table().contains_key(key).await;
+    }
+
+    /// Return the amount of keys in the default table
+    pub async fn len(&self) -> usize {
+        return self.default_table().len().await;
+    }
+
+    /// Check if the default table has no keys
+    pub async fn is_empty(&self) -> bool {
+        return self.default_table().is_empty().await;
+    }
+
+    /// Return an iterator over the keys and values of the default table
+    pub async fn iter(&self) -> Iter {
+        return self.default_table().iter().await;
+    }
+
+    /// Return an iterator over the keys of the default table
+    pub async fn keys(&self) -> Keys {
+        return self.default_table().keys().await;
+    }
+
+    /// Return the entries with a key that starts with `prefix`, sorted by key
+    pub async fn scan_prefix(&self, prefix: &str) -> Iter {
+        return self.default_table().scan_prefix(prefix).await;
+    }
+
+    /// Insert a document under a generated id and return the id
+    pub async fn insert_doc<T: Serialize + ?Sized>(&self, doc: &T) -> Result<DocId> {
+        return self.default_table().insert_doc(doc).await;
+    }
+
+    /// Get the document with the id `id` as any type that implements [DeserializeOwned]
+    pub async fn get_doc<T: DeserializeOwned + Send + 'static>(&self, id: DocId) -> Result<T> {
+        return self.default_table().get_doc(id).await;
+    }
+
+    /// Replace the document with the id `id`
+    pub async fn update_doc<T: Serialize + ?Sized>(&self, id: DocId, doc: &T) -> Result<()> {
+        return self.default_table().update_doc(id, doc).await;
+    }
+
+    /// Remove the document with the id `id`
+    pub async fn remove_doc(&self, id: DocId) -> Result<()> {
+        return self.default_table().remove_doc(id).await;
+    }
+
+    /// Apply a mongo style update to every entry of the default table that matches `filter`
+    pub async fn update(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.default_table().update(filter, update).await;
+    }
+
+    /// Like [AsyncDatabase::update] but insert a document when nothing matches
+    pub async fn upsert(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.default_table().upsert(filter, update).await;
+    }
+
+    /// Return the entries of the default table whose `field` is `value`
+    pub async fn find_by<T: Serialize + ?Sized>(&self, field: &str, value: &T) -> Result<Vec<(String, Value)>> {
+        return self.default_table().find_by(field, value).await;
+    }
+
+    /// Insert a key with a subtree in the default table
+    pub async fn insert_tree(&self, key: &str, value: Tree) -> Result<()> {
+        return self.default_table().insert_tree(key, value).await;
+    }
+
+    /// Let a key of the default table expire at `at`
+    pub async fn expire(&self, key: &str, at: SystemTime) -> Result<()> {
+        return self.default_table().expire(key, at).await;
+    }
+
+    /// Return how long a key of the default table has left before it expires
+    pub async fn ttl(&self, key: &str) -> Option<Duration> {
+        return self.default_table().ttl(key).await;
+    }
+
+    /// Return a channel that gets an [Event] for every change to a key of the default table that starts with `prefix`.
+    /// This does not block, but receive from the channel with [Receiver::try_recv] or on the blocking thread pool
+    pub fn subscribe(&self, prefix: &str) -> Receiver<Event> {
+        return self.default_table().subscribe(prefix);
+    }
+
+    /// Return an iterator over the values of the default table in key order
+    pub async fn values(&self) -> Values {
+        return self.default_table().values().await;
+    }
+
+    /// Return the entries of the default table with a key in `range` like `"a".."m"`, sorted by key
+    pub async fn range<'r, R: RangeBounds<&'r str>>(&self, range: R) -> Iter {
+        return self.default_table().range(range).await;
+    }
+
+    /// Return up to `limit` entries of the default table with a key that starts with `prefix`. See [Database::page]
+    pub async fn page(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Page> {
+        return self.default_table().page(prefix, after, limit).await;
+    }
+
+    /// Index a field of the entries of the default table. See [Table::create_index]
+    pub async fn create_index(&self, field: &str) -> Result<()> {
+        return self.default_table().create_index(field).await;
+    }
+
+    /// Index a field of the default table that no two entries may have the same value in
+    pub async fn create_unique_index(&self, field: &str) -> Result<()> {
+        return self.default_table().create_unique_index(field).await;
+    }
+
+    /// Remove the index of a field of the default table
+    pub async fn drop_index(&self, field: &str) -> Result<()> {
+        return self.default_table().drop_index(field).await;
+    }
+
+    /// Return the fields of the default table that have an index sorted by name
+    pub async fn indexes(&self) -> Vec<String> {
+        return self.default_table().indexes().await;
+    }
+
+    /// Check every entry of the default table against `schema` from now on. See [Table::set_schema]
+    pub async fn set_schema(&self, schema: Schema) -> Result<()> {
+        return self.default_table().set_schema(schema).await;
+    }
+
+    /// Stop checking the entries of the default table against a schema
+    pub async fn remove_schema(&self) -> Result<()> {
+        return self.default_table().remove_schema().await;
+    }
+
+    /// Return the schema of the default table if it has one
+    pub async fn schema(&self) -> Option<Schema> {
+        return self.default_table().schema().await;
+    }
+
+    /// Build a [Query] over the entries of the default table with `f` and run it on the blocking thread pool.
+    /// See [AsyncTable::query]
+    pub async fn query<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(Query<'_>) -> T + Send + 'static
+    {
+        return self.default_table().query(f).await;
+    }
+
+    /// Run `f` with the database on the blocking thread pool
+    async fn read<T: Send + 'static, F: FnOnce(&Database) -> T + Send + 'static>(&self, f: F) -> T {
+        let db = self.db.clone();
+
+        return blocking(move || f(&db)).await;
+    }
+
+    /// Run `f` with the database on the blocking thread pool once the writers before it are done
+    async fn write<T: Send + 'static, F: FnOnce(&Database) -> T + Send + 'static>(&self, f: F) -> T {
+        let _writing = self.writes.lock().await;
+
+        return self.read(f).await;
+    }
+}
+
+/// A handle to a table of an [AsyncDatabase] that is returned by [AsyncDatabase::table]
+/// It has the async versions of the methods of [Table]
+#[derive(Clone)]
+pub struct AsyncTable {
+    db: AsyncDatabase,
+    name: Option<String>
+}
+
+impl AsyncTable {
+    async fn read<T: Send + 'static, F: FnOnce(&Table) -> T + Send + 'static>(&self, f: F) -> T {
+        let name = self.name.clone();
+
+        return self.db.read(move |db| f(&Table::new(db, name.as_deref()))).await;
+    }
+
+    async fn write<T: Send + 'static, F: FnOnce(&Table) -> T + Send + 'static>(&self, f: F) -> T {
+        let name = self.name.clone();
+
+        return self.db.write(move |db| f(&Table::new(db, name.as_deref()))).await;
+    }
+
+    /// Insert a key and a value in the table
+    pub async fn insert<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        let (key, value) = (key.to_string(), serde_json::to_value(value)?);
+
+        return self.write(move |table| table.insert(&key, &value)).await;
+    }
+
+    /// Insert a key and a value in the table that expires after `ttl`
+    pub async fn insert_with_ttl<T: Serialize + ?Sized>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        let (key, value) = (key.to_string(), serde_json::to_value(value)?);
+
+        return self.write(move |table| table.insert_with_ttl(&key, &value, ttl)).await;
+    }
+
+    /// Remove a key in the table with its value
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        let key = key.to_string();
+
+        return self.write(move |table| table.remove(&key)).await;
+    }
+
+    /// Set the value at a path like `user/address/city` in the table
+    pub async fn set_path<T: Serialize + ?Sized>(&self, path: &str, value: &T) -> Result<()> {
+        let (path, value) = (path.to_string(), serde_json::to_value(value)?);
+
+        return self.write(move |table| table.set_path(&path, &value)).await;
+    }
+
+    /// Remove the value at a path in the table
+    pub async fn remove_path(&self, path: &str) -> Result<()> {
+        let path = path.to_string();
+
+        return self.write(move |table| table.remove_path(&path)).await;
+    }
+
+    /// Get a value in the table as any type that implements [DeserializeOwned]
+    pub async fn get<T: DeserializeOwned + Send + 'static>(&self, key: &str) -> Result<T> {
+        let key = key.to_string();
+
+        return self.read(move |table| table.get(&key)).await;
+    }
+
+    /// Find a value in the table
+    pub async fn find(&self, key: &str) -> Result<Value> {
+        let key = key.to_string();
+
+        return self.read(move |table| table.find(&key)).await;
+    }
+
+    /// Get the value at a path in the table as any type that implements [DeserializeOwned]
+    pub async fn get_path<T: DeserializeOwned + Send + 'static>(&self, path: &str) -> Result<T> {
+        let path = path.to_string();
+
+        return self.read(move |table| table.get_path(&path)).await;
+    }
+
+    /// Find the value at a path in the table
+    pub async fn find_path(&self, path: &str) -> Result<Value> {
+        let path = path.to_string();
+
+        return self.read(move |table| table.find_path(&path)).await;
+    }
+
+    /// Check if a key exists in the table
+    pub async fn contains_key(&self, key: &str) -> bool {
+        let key = key.to_string();
+
+        return self.read(move |table| table.contains_key(&key)).await;
+    }
+
+    /// Return the amount of keys in the table
+    pub async fn len(&self) -> usize {
+        return self.read(|table| table.len()).await;
+    }
+
+    /// Check if the table has no keys
+    pub async fn is_empty(&self) -> bool {
+        return self.read(|table| table.is_empty()).await;
+    }
+
+    /// Return an iterator over the keys and values of the table
+    pub async fn iter(&self) -> Iter {
+        return self.read(|table| table.iter()).await;
+    }
+
+    /// Return an iterator over the keys of the table
+    pub async fn keys(&self) -> Keys {
+        return self.read(|table| table.keys()).await;
+    }
+
+    /// Return the entries with a key that starts with `prefix`, sorted by key
+    pub async fn scan_prefix(&self, prefix: &str) -> Iter {
+        let prefix = prefix.to_string();
+
+        return self.read(move |table| table.scan_prefix(&prefix)).await;
+    }
+
+    /// Insert a document under a generated id and return the id
+    pub async fn insert_doc<T: Serialize + ?Sized>(&self, doc: &T) -> Result<DocId> {
+        let doc = serde_json::to_value(doc)?;
+
+        return self.write(move |table| table.insert_doc(&doc)).await;
+    }
+
+    /// Get the document with the id `id` as any type that implements [DeserializeOwned]
+    pub async fn get_doc<T: DeserializeOwned + Send + 'static>(&self, id: DocId) -> Result<T> {
+        return self.read(move |table| table.get_doc(id)).await;
+    }
+
+    /// Replace the document with the id `id`
+    pub async fn update_doc<T: Serialize + ?Sized>(&self, id: DocId, doc: &T) -> Result<()> {
+        let doc = serde_json::to_value(doc)?;
+
+        return self.write(move |table| table.update_doc(id, &doc)).await;
+    }
+
+    /// Remove the document with the id `id`
+    pub async fn remove_doc(&self, id: DocId) -> Result<()> {
+        return self.write(move |table| table.remove_doc(id)).await;
+    }
+
+    /// Apply a mongo style update to every entry that matches `filter`. See [Table::update]
+    pub async fn update(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        let (filter, update) = (filter.clone(), update.clone());
+
+        return self.write(move |table| table.update(&filter, &update)).await;
+    }
+
+    /// Like [AsyncTable::update] but insert a document when nothing matches
+    pub async fn upsert(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        let (filter, update) = (filter.clone(), update.clone());
+
+        return self.write(move |table| table.upsert(&filter, &update)).await;
+    }
+
+    /// Return the entries whose `field` is `value`. See [Table::find_by]
+    pub async fn find_by<T: Serialize + ?Sized>(&self, field: &str, value: &T) -> Result<Vec<(String, Value)>> {
+        let (field, value) = (field.to_string(), serde_json::to_value(value)?);
+
+        return self.read(move |table| table.find_by(&field, &value)).await;
+    }
+
+    /// Insert a key with a subtree in the table
+    pub async fn insert_tree(&self, key: &str, value: Tree) -> Result<()> {
+        let key = key.to_string();
+
+        return self.write(move |table| table.insert_tree(&key, value)).await;
+    }
+
+    /// Let a key of the table expire at `at`. See [Table::expire]
+    pub async fn expire(&self, key: &str, at: SystemTime) -> Result<()> {
+        let key = key.to_string();
+
+        return self.write(move |table| table.expire(&key, at)).await;
+    }
+
+    /// Return how long a key has left before it expires or [None] when it does not expire or does not exist
+    pub async fn ttl(&self, key: &str) -> Option<Duration> {
+        let key = key.to_string();
+
+        return self.read(move |table| table.ttl(&key)).await;
+    }
+
+    /// Return a channel that gets an [Event] for every change to a key of the table that starts with `prefix`.
+    /// This does not block, but receive from the channel with [Receiver::try_recv] or on the blocking thread pool
+    pub fn subscribe(&self, prefix: &str) -> Receiver<Event> {
+        return Table::new(&self.db.db, self.name.as_deref()).subscribe(prefix);
+    }
+
+    /// Return an iterator over the values of the table in key order
+    pub async fn values(&self) -> Values {
+        return self.read(|table| table.values()).await;
+    }
+
+    /// Return the entries with a key in `range` like `"a".."m"`, sorted by key
+    pub async fn range<'r, R: RangeBounds<&'r str>>(&self, range: R) -> Iter {
+        let (start, end) = owned(&range);
+
+        return self.read(move |table| table.range((start.as_ref().map(String::as_str), end.as_ref().map(String::as_str)))).await;
+    }
+
+    /// Return up to `limit` entries with a key that starts with `prefix`, sorted by key. See [Table::page]
+    pub async fn page(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Page> {
+        let (prefix, after) = (prefix.to_string(), after.map(String::from));
+
+        return self.read(move |table| table.page(&prefix, after.as_deref(), limit)).await;
+    }
+
+    /// Index a field of the entries of the table. See [Table::create_index]
+    pub async fn create_index(&self, field: &str) -> Result<()> {
+        let field = field.to_string();
+
+        return self.write(move |table| table.create_index(&field)).await;
+    }
+
+    /// Index a field that no two entries may have the same value in. See [Table::create_unique_index]
+    pub async fn create_unique_index(&self, field: &str) -> Result<()> {
+        let field = field.to_string();
+
+        return self.write(move |table| table.create_unique_index(&field)).await;
+    }
+
+    /// Remove the index of a field
+    pub async fn drop_index(&self, field: &str) -> Result<()> {
+        let field = field.to_string();
+
+        return self.write(move |table| table.drop_index(&field)).await;
+    }
+
+    /// Return the fields that have an index sorted by name
+    pub async fn indexes(&self) -> Vec<String> {
+        return self.read(|table| table.indexes()).await;
+    }
+
+    /// Check every entry of the table against `schema` from now on. See [Table::set_schema]
+    pub async fn set_schema(&self, schema: Schema) -> Result<()> {
+        return self.write(move |table| table.set_schema(schema)).await;
+    }
+
+    /// Stop checking the entries of the table against a schema
+    pub async fn remove_schema(&self) -> Result<()> {
+        return self.write(|table| table.remove_schema()).await;
+    }
+
+    /// Return the schema of the table if it has one
+    pub async fn schema(&self) -> Option<Schema> {
+        return self.read(|table| table.schema()).await;
+    }
+
+    /// Build a [Query] over the entries of the table with `f` and run it on the blocking thread pool.
+    /// A [Query] borrows the table so it cannot be held across an await, `f` builds and runs it in one go
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # tokio::runtime::Builder::new_multi_thread().build().unwrap().block_on(async {
+    /// # let db = AsyncDatabase::open(Database::in_memory()).await.unwrap();
+    /// let users = db.table("users");
+    /// users.insert("alice", &serde_json::json!({ "age": 30 })).await.unwrap();
+    ///
+    /// let adults = users.query(|query| query.where_("age").gt(17).run()).await.unwrap();
+    ///
+    /// assert_eq!(adults.len(), 1);
+    /// # });
+    /// ```
+    pub async fn query<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(Query<'_>) -> T + Send + 'static
+    {
+        let name = self.name.clone();
+
+        return self.db.read(move |db| f(Table::new(db, name.as_deref()).query())).await;
+    }
+}
@@ -0,0 +1,76 @@
+//! Grouping many changes so they are applied under one lock and saved in one go
+
+use serde::Serialize;
+
+use crate::journal::Record;
+use crate::{ inner_path, Result, Tree };
+
+/// A list of changes that is applied with [Database::apply_batch](crate::Database::apply_batch)
+/// Nothing happens to the database until the batch is applied
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # let db = Database::in_memory();
+/// let mut batch = WriteBatch::new();
+///
+/// for i in 0..10000 {
+///     batch.insert(&format!("key-{}", i), &i).unwrap();
+/// }
+///
+/// // A single save instead of 10000
+/// db.apply_batch(batch).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    pub(crate) records: Vec<Record>
+}
+
+impl WriteBatch {
+    /// Create a new empty batch
+    pub fn new() -> WriteBatch {
+        return WriteBatch {
+            records: Vec::new()
+        }
+    }
+
+    /// Insert a key and a value in the database
+    pub fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.records.push(Record::set(key, serde_json::to_value(value)?));
+
+        return Ok(());
+    }
+
+    /// Insert a key with a subtree in the database
+    pub fn insert_tree(&mut self, key: &str, value: Tree) {
+        self.records.push(Record::set(key, value.children.unwrap()));
+    }
+
+    /// Remove a key in the database with its value
+    pub fn remove(&mut self, key: &str) {
+        self.records.push(Record::remove(key));
+    }
+
+    /// Set the value at a path like `users/alice/age` in the database
+    pub fn set_path<T: Serialize + ?Sized>(&mut self, path: &str, value: &T) -> Result<()> {
+        self.records.push(Record::Set { path: inner_path(path)?, value: serde_json::to_value(value)? });
+
+        return Ok(());
+    }
+
+    /// Remove the value at a path like `users/alice/age` in the database
+    pub fn remove_path(&mut self, path: &str) -> Result<()> {
+        self.records.push(Record::Remove { path: inner_path(path)? });
+
+        return Ok(());
+    }
+
+    /// Return the amount of changes in the batch
+    pub fn len(&self) -> usize {
+        return self.records.len();
+    }
+
+    /// Check if the batch has no changes
+    pub fn is_empty(&self) -> bool {
+        return self.records.is_empty();
+    }
+}
@@ -0,0 +1,96 @@
+//! Compressing the snapshot of the main tree
+//!
+//! The snapshot is compressed after it is encoded in its [Format](crate::Format).
+//! zstd and gzip start with magic bytes of their own, so [Database::load](crate::Database::load)
+//! finds out how a snapshot is compressed by looking at them and plain snapshots keep working.
+//! Every compression is behind a cargo feature of the same name: `zstd` and `gzip`.
+//! The journal is never compressed.
+
+use crate::{ Error, Result };
+
+/// The first bytes of a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The first bytes of a gzip member
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// How the snapshot of the main tree is compressed. Set it with [Database::set_compression](crate::Database::set_compression)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// The snapshot is saved as it is. This is the default
+    #[default]
+    None,
+
+    /// zstd at its default level with the `zstd` feature
+    #[cfg(feature = "zstd")]
+    Zstd,
+
+    /// gzip at its default level with the `gzip` feature
+    #[cfg(feature = "gzip")]
+    Gzip
+}
+
+/// Compress an encoded snapshot
+pub(crate) fn compress(snapshot: Vec<u8>, compression: Compression) -> Result<Vec<u8>> {
+    return match compression {
+        Compression::None => Ok(snapshot),
+
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            zstd::encode_all(snapshot.as_slice(), 0).map_err(|error| failed("zstd", error))
+        },
+
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&snapshot).map_err(|error| failed("gzip", error))?;
+
+            encoder.finish().map_err(|error| failed("gzip", error))
+        }
+    }
+}
+
+/// Decompress a snapshot that was saved with any compression. A plain snapshot comes back as it is
+pub(crate) fn decompress(snapshot: Vec<u8>) -> Result<Vec<u8>> {
+    if snapshot.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        return zstd::decode_all(snapshot.as_slice()).map_err(|error| failed("zstd", error));
+
+        #[cfg(not(feature = "zstd"))]
+        return Err(disabled("zstd"));
+    }
+
+    if snapshot.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Read;
+
+            let mut decompressed = Vec::new();
+
+            flate2::read::GzDecoder::new(snapshot.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(|error| failed("gzip", error))?;
+
+            return Ok(decompressed);
+        }
+
+        #[cfg(not(feature = "gzip"))]
+        return Err(disabled("gzip"));
+    }
+
+    return Ok(snapshot);
+}
+
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+fn failed(compression: &'static str, error: std::io::Error) -> Error {
+    return Error::Encoding { format: compression, reason: error.to_string() };
+}
+
+/// The error for a snapshot that was compressed with a feature that is not enabled
+#[cfg(not(all(feature = "zstd", feature = "gzip")))]
+fn disabled(compression: &'static str) -> Error {
+    return Error::Encoding { format: compression, reason: format!("the `{}` feature of dino is not enabled", compression) };
+}
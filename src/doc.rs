@@ -0,0 +1,55 @@
+//! Ids for the documents of the document store mode
+//!
+//! [Table::insert_doc](crate::Table::insert_doc) stores a document under the next id of its table.
+//! The next id of every table is kept in the `$meta` key of the main tree so ids are never reused,
+//! not even after the document with the highest id was removed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ Error, Result };
+
+/// The key of the main tree that holds what dino knows about its tables
+pub(crate) const META: &str = "$meta";
+
+/// The id of a document that was inserted with [Table::insert_doc](crate::Table::insert_doc)
+/// The document is stored under the id as a key so it also works with [Table::get](crate::Table::get)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DocId(pub u64);
+
+impl DocId {
+    /// The key that the document is stored under
+    pub fn key(&self) -> String {
+        return self.0.to_string();
+    }
+}
+
+/// The path in the main tree of the metadata of a table. The default table is [None]
+pub(crate) fn meta_path(table: Option<&str>) -> Vec<String> {
+    return match table {
+        Some(name) => vec![META.to_string(), "tables".to_string(), name.to_string()],
+        None => vec![META.to_string(), "default".to_string()]
+    }
+}
+
+/// The path in the main tree of the next document id of a table
+pub(crate) fn counter_path(table: Option<&str>) -> Vec<String> {
+    let mut path = meta_path(table);
+    path.push("next_id".to_string());
+
+    return path;
+}
+
+impl fmt::Display for DocId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for DocId {
+    type Err = Error;
+
+    fn from_str(id: &str) -> Result<DocId> {
+        return id.parse().map(DocId).map_err(|_| Error::InvalidId(id.to_string()));
+    }
+}
@@ -0,0 +1,237 @@
+//! Encrypting the database at rest
+//!
+//! With a key set by [Database::open_encrypted](crate::Database::open_encrypted) the snapshot and
+//! every journal record are sealed with XChaCha20-Poly1305 under a random nonce. A sealed snapshot
+//! starts with the magic bytes `DINO` and the byte `0x80`, followed by the generation of the snapshot
+//! before it, the nonce and the cipher text. A sealed record is the nonce and the cipher text in hex,
+//! so it stays on one line of the journal. Every record is sealed with the generation of the snapshot
+//! it follows and its place in the journal, so records cannot be dropped, reordered or replayed.
+//! A wrong key or data that was changed gives [Error::Decryption] instead of garbage.
+//! The cipher is behind the `encryption` cargo feature.
+
+use crate::{ Error, Result };
+
+/// The bytes every sealed snapshot starts with
+const MAGIC: &[u8] = b"DINO\x80";
+
+/// Sealing a snapshot and sealing a record use other associated data, so one cannot pass for the other
+#[cfg(feature = "encryption")]
+const SNAPSHOT: &[u8] = b"dino snapshot";
+
+#[cfg(feature = "encryption")]
+const RECORD: &[u8] = b"dino record";
+
+/// The length of a XChaCha20-Poly1305 nonce
+#[cfg(feature = "encryption")]
+const NONCE: usize = 24;
+
+/// The snapshot that the records of the journal follow. This is the nonce of a sealed snapshot,
+/// so every save starts a new generation, and zeros when the snapshot is not sealed or there is none yet
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Generation([u8; 24]);
+
+/// The generation of a snapshot and the one of the snapshot that was saved before it
+#[derive(Debug, Default)]
+pub(crate) struct Generations {
+    current: Generation,
+    previous: Generation
+}
+
+/// The cipher of a database with a key
+#[cfg(feature = "encryption")]
+pub(crate) struct Cipher(chacha20poly1305::XChaCha20Poly1305);
+
+/// There is no cipher without the `encryption` feature
+#[cfg(not(feature = "encryption"))]
+pub(crate) enum Cipher {}
+
+#[cfg(feature = "encryption")]
+impl Cipher {
+    pub(crate) fn new(key: &[u8; 32]) -> Cipher {
+        use chacha20poly1305::KeyInit;
+
+        return Cipher(chacha20poly1305::XChaCha20Poly1305::new(key.into()));
+    }
+
+    /// The nonce and the cipher text of `plain`
+    fn seal(&self, plain: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        use chacha20poly1305::aead::{ Aead, AeadCore, OsRng, Payload };
+
+        let nonce = chacha20poly1305::XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let sealed = self.0.encrypt(&nonce, Payload { msg: plain, aad })
+            .map_err(|_| Error::Encryption(String::from("the data is too long for the cipher")))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&sealed);
+
+        return Ok(data);
+    }
+
+    fn open(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        use chacha20poly1305::aead::{ Aead, Payload };
+
+        if data.len() < NONCE {
+            return Err(tampered());
+        }
+
+        let (nonce, sealed) = data.split_at(NONCE);
+
+        return self.0.decrypt(nonce.into(), Payload { msg: sealed, aad }).map_err(|_| tampered());
+    }
+}
+
+/// Seal a snapshot when the database has a key and return it with its generation.
+/// `previous` is the generation that the records in the journal are sealed under
+pub(crate) fn seal_snapshot(cipher: Option<&Cipher>, previous: Generation, snapshot: Vec<u8>) -> Result<(Vec<u8>, Generation)> {
+    return match cipher {
+        None => Ok((snapshot, Generation::default())),
+
+        #[cfg(feature = "encryption")]
+        Some(cipher) => {
+            let sealed = cipher.seal(&snapshot, &[SNAPSHOT, &previous.0].concat())?;
+            let generation = generation(&sealed);
+
+            let mut data = MAGIC.to_vec();
+            data.extend_from_slice(&previous.0);
+            data.extend(sealed);
+
+            Ok((data, generation))
+        },
+
+        #[cfg(not(feature = "encryption"))]
+        Some(cipher) => {
+            let _ = previous;
+
+            match *cipher {}
+        }
+    }
+}
+
+/// Open a snapshot that was sealed with the key of the database. A database with a key only takes sealed snapshots
+pub(crate) fn open_snapshot(cipher: Option<&Cipher>, snapshot: Vec<u8>) -> Result<(Vec<u8>, Generations)> {
+    return match (cipher, snapshot.strip_prefix(MAGIC)) {
+        (None, None) => Ok((snapshot, Generations::default())),
+        (None, Some(_)) => Err(encrypted()),
+        (Some(_), None) => Err(Error::Decryption(String::from("the database is not encrypted"))),
+
+        #[cfg(feature = "encryption")]
+        (Some(cipher), Some(sealed)) => {
+            if sealed.len() < NONCE {
+                return Err(tampered());
+            }
+
+            let (previous, sealed) = sealed.split_at(NONCE);
+            let plain = cipher.open(sealed, &[SNAPSHOT, previous].concat())?;
+
+            Ok((plain, Generations { current: generation(sealed), previous: generation(previous) }))
+        },
+
+        #[cfg(not(feature = "encryption"))]
+        (Some(cipher), Some(_)) => match *cipher {}
+    }
+}
+
+/// Seal a journal record when the database has a key. `sequence` is the amount of records before it since the snapshot of `generation`
+pub(crate) fn seal_record(cipher: Option<&Cipher>, generation: Generation, sequence: usize, record: Vec<u8>) -> Result<Vec<u8>> {
+    return match cipher {
+        None => Ok(record),
+
+        #[cfg(feature = "encryption")]
+        Some(cipher) => Ok(hex(&cipher.seal(&record, &place(generation, sequence))?)),
+
+        #[cfg(not(feature = "encryption"))]
+        Some(cipher) => {
+            let _ = (generation, sequence);
+
+            match *cipher {}
+        }
+    }
+}
+
+/// Open the records of the journal and return them with the generation they are sealed under.
+/// That is the generation of the snapshot, or the one before it when the journal was not cleared after the
+/// snapshot was saved. Those records are in the snapshot already, so replaying them again changes nothing.
+/// A record that is not at its place gives [Error::Decryption], but records that were cut off the end are not noticed
+pub(crate) fn open_records(cipher: Option<&Cipher>, generations: &Generations, records: &[Vec<u8>]) -> Result<(Vec<Vec<u8>>, Generation)> {
+    let mut generation = generations.current;
+
+    if records.first().is_some_and(|first| open_record(cipher, generation, 0, first).is_err()) {
+        generation = generations.previous;
+    }
+
+    let opened = records.iter().enumerate()
+        .map(|(sequence, record)| open_record(cipher, generation, sequence, record))
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+
+    return Ok((opened, generation));
+}
+
+/// Open a journal record that was sealed with the key of the database at its place.
+/// A plain record is json, so it never consists of hex digits only like a sealed one
+fn open_record(cipher: Option<&Cipher>, generation: Generation, sequence: usize, record: &[u8]) -> Result<Vec<u8>> {
+    let sealed = !record.is_empty() && record.iter().all(u8::is_ascii_hexdigit);
+
+    return match (cipher, sealed) {
+        (None, false) => Ok(record.to_vec()),
+        (None, true) => Err(encrypted()),
+        (Some(_), false) => Err(Error::Decryption(String::from("the journal is not encrypted"))),
+
+        #[cfg(feature = "encryption")]
+        (Some(cipher), true) => cipher.open(&unhex(record).ok_or_else(tampered)?, &place(generation, sequence)),
+
+        #[cfg(not(feature = "encryption"))]
+        (Some(cipher), true) => {
+            let _ = (generation, sequence);
+
+            match *cipher {}
+        }
+    }
+}
+
+/// The associated data of the record at `sequence` after the snapshot of `generation`
+#[cfg(feature = "encryption")]
+fn place(generation: Generation, sequence: usize) -> Vec<u8> {
+    return [RECORD, &generation.0, &(sequence as u64).to_be_bytes()].concat();
+}
+
+/// The generation of a snapshot from its nonce at the start of `sealed`
+#[cfg(feature = "encryption")]
+fn generation(sealed: &[u8]) -> Generation {
+    let mut generation = Generation::default();
+    generation.0.copy_from_slice(&sealed[..NONCE]);
+
+    return generation;
+}
+
+/// The error for sealed data that is opened without a key
+fn encrypted() -> Error {
+    if cfg!(feature = "encryption") {
+        return Error::Decryption(String::from("the database is encrypted, open it with `Database::open_encrypted`"));
+    }
+
+    return Error::Decryption(String::from("the database is encrypted and the `encryption` feature of dino is not enabled"));
+}
+
+#[cfg(feature = "encryption")]
+fn tampered() -> Error {
+    return Error::Decryption(String::from("the key is wrong or the data was changed"));
+}
+
+#[cfg(feature = "encryption")]
+fn hex(data: &[u8]) -> Vec<u8> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    return data.iter().flat_map(|byte| [DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0xf) as usize]]).collect();
+}
+
+#[cfg(feature = "encryption")]
+fn unhex(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+
+    return data.chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect();
+}
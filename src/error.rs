@@ -0,0 +1,198 @@
+//! The errors that dino can return
+
+use std::fmt;
+use std::io;
+
+/// A [Result](std::result::Result) with the dino [Error] as the error type
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong in dino
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing the database file failed
+    Io(io::Error),
+
+    /// The database file is not valid json or a value cannot be converted to or from json
+    Parse(serde_json::Error),
+
+    /// The snapshot cannot be written or read in its [Format](crate::Format) or with its [Compression](crate::Compression), like a snapshot in a format whose cargo feature is not enabled
+    Encoding {
+        format: &'static str,
+        reason: String
+    },
+
+    /// The database cannot be decrypted, because the key is wrong, the data was changed or it is encrypted and no key was given
+    Decryption(String),
+
+    /// The database cannot be encrypted with its key
+    Encryption(String),
+
+    /// The key does not exist in the database or in the sub tree
+    NotFound(String),
+
+    /// Something with this name exists already, like the target table of [Database::rename_table](crate::Database::rename_table)
+    AlreadyExists(String),
+
+    /// A change would give two entries the same value in a field with a unique index
+    Duplicate {
+        field: String,
+        value: String
+    },
+
+    /// A change would make an entry not match the schema of its table. The path points at the part that does not match
+    Invalid {
+        path: String,
+        reason: String
+    },
+
+    /// A [Schema](crate::Schema) cannot be used, like one with a type that does not exist
+    InvalidSchema(String),
+
+    /// The value has another type than the one that was asked for
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str
+    },
+
+    /// The string is not a valid [DocId](crate::DocId)
+    InvalidId(String),
+
+    /// An `$inc` of an update would take the integer at the path out of the range of [i64] and [u64],
+    /// or the float at the path to infinity
+    Overflow(String),
+
+    /// The path is not a valid json pointer or points past the end of an array
+    InvalidPath(String),
+
+    /// A [Query](crate::Query) has a condition that cannot be used, like a regular expression that is not valid
+    InvalidQuery(String),
+
+    /// Another process holds the lock on the database file
+    Locked(String),
+
+    /// The database was opened read only and cannot be changed
+    ReadOnly,
+
+    /// The database was used before [Database::load](crate::Database::load) was called
+    NotLoaded
+}
+
+impl Error {
+    /// A [Error::TypeMismatch] for a json value that is not of the `expected` type
+    pub(crate) fn mismatch(expected: &'static str, found: &serde_json::Value) -> Error {
+        return Error::TypeMismatch { expected, found: type_name(found) };
+    }
+}
+
+/// The name of the type of a json value as dino calls it
+pub(crate) fn type_name(value: &serde_json::Value) -> &'static str {
+    return match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "bool",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "tree"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => {
+                write!(f, "Cannot read or write the database: {}", error)
+            },
+
+            Error::Parse(error) => {
+                write!(f, "Invalid json: {}", error)
+            },
+
+            Error::Encoding { format, reason } => {
+                write!(f, "Cannot encode or decode the {} snapshot: {}", format, reason)
+            },
+
+            Error::Decryption(reason) => {
+                write!(f, "Cannot decrypt the database: {}", reason)
+            },
+
+            Error::Encryption(reason) => {
+                write!(f, "Cannot encrypt the database: {}", reason)
+            },
+
+            Error::NotFound(key) => {
+                write!(f, "The key `{}` does not exist in the database. You might want to create this or handle the error!", key)
+            },
+
+            Error::AlreadyExists(name) => {
+                write!(f, "`{}` exists already", name)
+            },
+
+            Error::Duplicate { field, value } => {
+                write!(f, "The value {} of the field `{}` is already used by another entry", value, field)
+            },
+
+            Error::Invalid { path, reason } => {
+                write!(f, "The value at `{}` does not match the schema: {}", path, reason)
+            },
+
+            Error::InvalidSchema(reason) => {
+                write!(f, "Invalid schema: {}", reason)
+            },
+
+            Error::TypeMismatch { expected, found } => {
+                write!(f, "Expected a {} but found a {}", expected, found)
+            },
+
+            Error::InvalidId(id) => {
+                write!(f, "`{}` is not a valid document id", id)
+            },
+
+            Error::Overflow(path) => {
+                write!(f, "The number at `{}` would overflow", path)
+            },
+
+            Error::InvalidPath(path) => {
+                write!(f, "The path `{}` is not valid", path)
+            },
+
+            Error::InvalidQuery(reason) => {
+                write!(f, "Invalid query: {}", reason)
+            },
+
+            Error::Locked(path) => {
+                write!(f, "The database `{}` is locked by another process", path)
+            },
+
+            Error::ReadOnly => {
+                write!(f, "The database is opened read only")
+            },
+
+            Error::NotLoaded => {
+                write!(f, "The database is not loaded. Call `Database::load` first!")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io(error) => Some(error),
+            Error::Parse(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        return Error::Io(error);
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        return Error::Parse(error);
+    }
+}
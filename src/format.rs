@@ -0,0 +1,198 @@
+//! The encodings of the snapshot of the main tree
+//!
+//! Json snapshots are written as they are, so a database file stays readable for people and
+//! for older versions of dino. The binary formats start with a header of the magic bytes
+//! `DINO` and a byte for the format, so [Database::load](crate::Database::load) picks the
+//! right decoder no matter which format the database is set to. Every binary format is
+//! behind a cargo feature of the same name: `msgpack`, `cbor` and `bincode`.
+//! The journal is always json.
+
+use crate::{ Error, Result };
+
+/// The bytes every snapshot in a binary format starts with
+const MAGIC: &[u8; 4] = b"DINO";
+
+/// How the snapshot of the main tree is encoded. Set it with [Database::set_format](crate::Database::set_format)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// Pretty printed json. This is the default
+    #[default]
+    Json,
+
+    /// MessagePack with the `msgpack` feature
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+
+    /// CBOR with the `cbor` feature
+    #[cfg(feature = "cbor")]
+    Cbor,
+
+    /// bincode with the `bincode` feature
+    #[cfg(feature = "bincode")]
+    Bincode
+}
+
+/// The format bytes of the header, with the names of their features for the error when one is not enabled
+const TAGS: [(u8, &str); 3] = [(1, "msgpack"), (2, "cbor"), (3, "bincode")];
+
+impl Format {
+    /// The name of the format as it is used in errors
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "bincode"))]
+    fn name(&self) -> &'static str {
+        return match self {
+            Format::Json => "json",
+
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => "msgpack",
+
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "cbor",
+
+            #[cfg(feature = "bincode")]
+            Format::Bincode => "bincode"
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Format> {
+        return match tag {
+            #[cfg(feature = "msgpack")]
+            1 => Ok(Format::MessagePack),
+
+            #[cfg(feature = "cbor")]
+            2 => Ok(Format::Cbor),
+
+            #[cfg(feature = "bincode")]
+            3 => Ok(Format::Bincode),
+
+            tag => {
+                let reason = match TAGS.iter().find(|(known, _)| *known == tag) {
+                    Some((_, feature)) => format!("the `{}` feature of dino is not enabled", feature),
+                    None => format!("the format {} is not known", tag)
+                };
+
+                Err(Error::Encoding { format: "binary", reason })
+            }
+        }
+    }
+}
+
+/// Encode the main tree as a snapshot in `format`
+pub(crate) fn encode(json: &serde_json::Value, format: Format) -> Result<Vec<u8>> {
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "bincode"))]
+    let failed = |reason: String| Error::Encoding { format: format.name(), reason };
+
+    return match format {
+        Format::Json => Ok(serde_json::to_vec_pretty(json)?),
+
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack => {
+            let mut snapshot = header(1);
+            rmp_serde::encode::write(&mut snapshot, json).map_err(|error| failed(error.to_string()))?;
+
+            Ok(snapshot)
+        },
+
+        #[cfg(feature = "cbor")]
+        Format::Cbor => {
+            let mut snapshot = header(2);
+            ciborium::into_writer(json, &mut snapshot).map_err(|error| failed(error.to_string()))?;
+
+            Ok(snapshot)
+        },
+
+        #[cfg(feature = "bincode")]
+        Format::Bincode => {
+            let mut snapshot = header(3);
+            bincode::serialize_into(&mut snapshot, &node::Node::from(json)).map_err(|error| failed(error.to_string()))?;
+
+            Ok(snapshot)
+        }
+    }
+}
+
+/// The start of a snapshot in the binary format with the `tag`
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "bincode"))]
+fn header(tag: u8) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(tag);
+
+    return header;
+}
+
+/// Decode a snapshot in any format
+pub(crate) fn decode(snapshot: &[u8]) -> Result<serde_json::Value> {
+    let (format, body) = match snapshot.strip_prefix(MAGIC) {
+        Some([tag, body @ ..]) => (Format::from_tag(*tag)?, body),
+        _ => return Ok(serde_json::from_slice(snapshot)?)
+    };
+
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "bincode"))]
+    let failed = |reason: String| Error::Encoding { format: format.name(), reason };
+
+    return match format {
+        Format::Json => Ok(serde_json::from_slice(body)?),
+
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack => rmp_serde::from_slice(body).map_err(|error| failed(error.to_string())),
+
+        #[cfg(feature = "cbor")]
+        Format::Cbor => ciborium::from_reader(body).map_err(|error| failed(error.to_string())),
+
+        #[cfg(feature = "bincode")]
+        Format::Bincode => bincode::deserialize::<node::Node>(body).map(serde_json::Value::from).map_err(|error| failed(error.to_string()))
+    }
+}
+
+/// bincode is not self describing, so it cannot read a [serde_json::Value] back.
+/// The tree goes through a mirror of it that says what every value is
+#[cfg(feature = "bincode")]
+mod node {
+    use serde::{ Deserialize, Serialize };
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) enum Node {
+        Null,
+        Bool(bool),
+        Int(i64),
+        Uint(u64),
+        Float(f64),
+        String(String),
+        Array(Vec<Node>),
+        Tree(Vec<(String, Node)>)
+    }
+
+    impl From<&serde_json::Value> for Node {
+        fn from(value: &serde_json::Value) -> Node {
+            return match value {
+                serde_json::Value::Null => Node::Null,
+                serde_json::Value::Bool(bool) => Node::Bool(*bool),
+
+                serde_json::Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+                    (Some(int), _) => Node::Int(int),
+                    (_, Some(uint)) => Node::Uint(uint),
+                    _ => Node::Float(number.as_f64().unwrap_or(0.0))
+                },
+
+                serde_json::Value::String(string) => Node::String(string.clone()),
+                serde_json::Value::Array(array) => Node::Array(array.iter().map(Node::from).collect()),
+                serde_json::Value::Object(tree) => Node::Tree(tree.iter().map(|(key, value)| (key.clone(), Node::from(value))).collect())
+            }
+        }
+    }
+
+    impl From<Node> for serde_json::Value {
+        fn from(node: Node) -> serde_json::Value {
+            return match node {
+                Node::Null => serde_json::Value::Null,
+                Node::Bool(bool) => serde_json::Value::Bool(bool),
+                Node::Int(int) => serde_json::Value::from(int),
+                Node::Uint(uint) => serde_json::Value::from(uint),
+                Node::Float(float) => serde_json::Value::from(float),
+                Node::String(string) => serde_json::Value::String(string),
+                Node::Array(array) => serde_json::Value::Array(array.into_iter().map(serde_json::Value::from).collect()),
+                Node::Tree(tree) => serde_json::Value::Object(tree.into_iter().map(|(key, node)| (key, serde_json::Value::from(node))).collect())
+            }
+        }
+    }
+}
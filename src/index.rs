@@ -0,0 +1,336 @@
+//! Secondary indexes on the fields of the entries of a table
+//!
+//! Only the definitions of the indexes are saved, in the `$meta` key of the main tree.
+//! The indexes themselves live in memory. They are built on [Database::load](crate::Database::load)
+//! and kept up to date with every change, so a change that would break a unique index fails.
+//! Keys that have expired but are not purged yet stay in the indexes, but do not hold on to their unique values.
+//! The keys of every table are kept in order here too, so scans and pages start right at their first key.
+
+use std::collections::{ BTreeSet, HashMap, HashSet };
+
+use crate::doc::{ self, META };
+use crate::journal::Record;
+use crate::table::{ self, touched, Touched };
+use crate::{ pointer, ttl, Error, Result };
+
+/// The path in the main tree of the index definitions of a table. The default table is [None]
+pub(crate) fn definitions_path(table: Option<&str>) -> Vec<String> {
+    let mut path = doc::meta_path(table);
+    path.push("indexes".to_string());
+
+    return path;
+}
+
+/// The text that a value is indexed by. Numbers without a fraction are written as integers so `30.0` finds `30`
+fn encode(value: &serde_json::Value) -> String {
+    if let Some(number) = value.as_f64() {
+        if number.fract() == 0.0 && number.abs() < 9007199254740992.0 {
+            return (number as i64).to_string();
+        }
+    }
+
+    return value.to_string();
+}
+
+/// An index of one field of the entries of a table
+#[derive(Debug)]
+pub(crate) struct Index {
+    unique: bool,
+
+    /// The path of the field inside of every entry
+    path: Vec<String>,
+
+    /// The keys of the entries by the encoded value of their field
+    values: HashMap<String, BTreeSet<String>>,
+
+    /// The encoded value of the field by key, to find the old value when an entry changes
+    keys: HashMap<String, String>
+}
+
+impl Index {
+    /// Build the index of `field` over the entries of a table. `now` is the time in milliseconds that keys expire by
+    fn build(root: &serde_json::Value, table: Option<&str>, field: &str, unique: bool, now: u64) -> Result<Index> {
+        let mut index = Index {
+            unique,
+            path: pointer::parse(field)?,
+            values: HashMap::new(),
+            keys: HashMap::new()
+        };
+
+        let expires = ttl::expires(root, table);
+
+        for (key, entry) in table::entries(root, table) {
+            if let Some(value) = pointer::get(entry, &index.path).map(encode) {
+                let live = |key: &String| !ttl::is_expired(expires, key, now);
+
+                if index.unique && live(key) && index.values.get(&value).is_some_and(|keys| keys.iter().any(live)) {
+                    return Err(Error::Duplicate { field: field.to_string(), value });
+                }
+
+                index.insert(key.clone(), value);
+            }
+        }
+
+        return Ok(index);
+    }
+
+    fn insert(&mut self, key: String, value: String) {
+        self.values.entry(value.clone()).or_default().insert(key.clone());
+        self.keys.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(value) = self.keys.remove(key) {
+            if let Some(keys) = self.values.get_mut(&value) {
+                keys.remove(key);
+
+                if keys.is_empty() {
+                    self.values.remove(&value);
+                }
+            }
+        }
+    }
+
+    /// The keys of the entries with `value` in the field, in key order
+    pub(crate) fn get(&self, value: &serde_json::Value) -> impl Iterator<Item = &String> {
+        return self.values.get(&encode(value)).into_iter().flatten();
+    }
+}
+
+/// The keys of a table that has none
+static NO_KEYS: BTreeSet<String> = BTreeSet::new();
+
+/// All of the indexes of a database by table and field
+#[derive(Debug, Default)]
+pub(crate) struct Indexes {
+    tables: HashMap<Option<String>, HashMap<String, Index>>,
+
+    /// The keys of the entries of every table in order
+    keys: HashMap<Option<String>, BTreeSet<String>>
+}
+
+impl Indexes {
+    /// Build every index that is defined in the main tree
+    pub(crate) fn load(root: &serde_json::Value, now: u64) -> Result<Indexes> {
+        let mut indexes = Indexes::default();
+        indexes.refresh(root, &[Record::Remove { path: vec![META.to_string()] }], now)?;
+        indexes.order(root, &[Touched::Tables]);
+
+        return Ok(indexes);
+    }
+
+    /// The index of `field` in a table if there is one
+    pub(crate) fn get(&self, table: Option<&str>, field: &str) -> Option<&Index> {
+        return self.tables.get(&table.map(String::from)).and_then(|fields| fields.get(field));
+    }
+
+    /// The keys of a table in order, with the ones that have expired
+    pub(crate) fn keys(&self, table: Option<&str>) -> &BTreeSet<String> {
+        return self.keys.get(&table.map(String::from)).unwrap_or(&NO_KEYS);
+    }
+
+    /// The fields of the indexes of a table
+    pub(crate) fn fields(&self, table: Option<&str>) -> Vec<String> {
+        let mut fields: Vec<String> = self.tables.get(&table.map(String::from))
+            .map_or_else(Vec::new, |fields| fields.keys().cloned().collect());
+
+        fields.sort();
+
+        return fields;
+    }
+
+    /// Bring the indexes up to date after `records` were applied to `root`.
+    /// When a unique index would be broken an error is returned and the indexes are left as they were.
+    /// `now` is the time in milliseconds that keys expire by
+    pub(crate) fn refresh(&mut self, root: &serde_json::Value, records: &[Record], now: u64) -> Result<()> {
+        let touched: Vec<Touched> = records.iter().map(|record| touched(record.path())).collect();
+
+        // Tables that were replaced as a whole and indexes that were added are built from scratch
+        let mut rebuilt: HashMap<(Option<String>, String), Index> = HashMap::new();
+        let mut dropped: HashSet<(Option<String>, String)> = HashSet::new();
+
+        if touched.iter().any(|touched| matches!(touched, Touched::Meta)) {
+            self.sync(root, &mut rebuilt, &mut dropped, now)?;
+        }
+
+        for touched in &touched {
+            let tables: Vec<String> = match touched {
+                Touched::Table(name) => vec![name.clone()],
+                Touched::Tables => self.tables.keys().flatten().cloned().collect(),
+                _ => continue
+            };
+
+            for name in tables {
+                let table = Some(name);
+
+                for (field, index) in self.tables.get(&table).into_iter().flatten() {
+                    let id = (table.clone(), field.clone());
+
+                    if !rebuilt.contains_key(&id) && !dropped.contains(&id) {
+                        let index = Index::build(root, table.as_deref(), field, index.unique, now)?;
+                        rebuilt.insert(id, index);
+                    }
+                }
+            }
+        }
+
+        // The entries that changed get their new value in the other indexes, by table and field and then by key
+        let mut changes: HashMap<(Option<String>, String), HashMap<String, Option<String>>> = HashMap::new();
+
+        for touched in &touched {
+            if let Touched::Entry(table, key) = touched {
+                for (field, index) in self.tables.get(table).into_iter().flatten() {
+                    let id = (table.clone(), field.clone());
+
+                    if rebuilt.contains_key(&id) {
+                        continue;
+                    }
+
+                    let keys = changes.entry(id).or_default();
+
+                    if !keys.contains_key(key) {
+                        let value = table::entry(root, table.as_deref(), key)
+                            .and_then(|entry| pointer::get(entry, &index.path))
+                            .map(encode);
+
+                        keys.insert(key.clone(), value);
+                    }
+                }
+            }
+        }
+
+        for ((table, field), keys) in &changes {
+            let index = &self.tables[table][field];
+
+            if !index.unique {
+                continue;
+            }
+
+            // Expired keys that are not purged yet are invisible, so their values are free to take
+            let expires = ttl::expires(root, table.as_deref());
+            let live = |key: &String| !ttl::is_expired(expires, key, now);
+
+            // The number of changed entries that get each value
+            let mut staged: HashMap<&String, usize> = HashMap::new();
+
+            for (key, value) in keys {
+                if let (true, Some(value)) = (live(key), value) {
+                    *staged.entry(value).or_default() += 1;
+                }
+            }
+
+            for (value, staged) in staged {
+                // The entries that keep this value
+                let kept = index.values.get(value).into_iter().flatten()
+                    .filter(|other| live(other) && !keys.contains_key(*other))
+                    .count();
+
+                if kept + staged > 1 {
+                    return Err(Error::Duplicate { field: field.clone(), value: value.clone() });
+                }
+            }
+        }
+
+        for (table, field) in dropped {
+            if let Some(fields) = self.tables.get_mut(&table) {
+                fields.remove(&field);
+            }
+        }
+
+        for ((table, field), index) in rebuilt {
+            self.tables.entry(table).or_default().insert(field, index);
+        }
+
+        self.tables.retain(|_, fields| !fields.is_empty());
+
+        for ((table, field), keys) in changes {
+            let index = self.tables.get_mut(&table).unwrap().get_mut(&field).unwrap();
+
+            for (key, value) in keys {
+                index.remove(&key);
+
+                if let Some(value) = value {
+                    index.insert(key, value);
+                }
+            }
+        }
+
+        self.order(root, &touched);
+
+        return Ok(());
+    }
+
+    /// Bring the keys of the tables in order up to date with the tables that were touched
+    fn order(&mut self, root: &serde_json::Value, touched: &[Touched]) {
+        for touched in touched {
+            match touched {
+                Touched::Entry(table, key) => {
+                    if table::entry(root, table.as_deref(), key).is_some() {
+                        self.keys.entry(table.clone()).or_default().insert(key.clone());
+                    } else if let Some(keys) = self.keys.get_mut(table) {
+                        keys.remove(key);
+                    }
+                },
+
+                Touched::Table(name) => {
+                    self.keys.insert(Some(name.clone()), table::entries(root, Some(name)).map(|(key, _)| key.clone()).collect());
+                },
+
+                // The whole tree was replaced or every named table, so all of the keys are read again
+                Touched::Tables => {
+                    self.keys.clear();
+                    self.keys.insert(None, table::entries(root, None).map(|(key, _)| key.clone()).collect());
+
+                    for name in root.get(table::TABLES).and_then(|tables| tables.as_object()).into_iter().flat_map(|tables| tables.keys()) {
+                        self.keys.insert(Some(name.clone()), table::entries(root, Some(name)).map(|(key, _)| key.clone()).collect());
+                    }
+                },
+
+                Touched::Meta => ()
+            }
+        }
+
+        self.keys.retain(|_, keys| !keys.is_empty());
+    }
+
+    /// Build the indexes that were added to the definitions and find the ones that were removed
+    fn sync(&self, root: &serde_json::Value, rebuilt: &mut HashMap<(Option<String>, String), Index>, dropped: &mut HashSet<(Option<String>, String)>, now: u64) -> Result<()> {
+        let mut tables: Vec<Option<String>> = vec![None];
+
+        if let Some(named) = root.get(META).and_then(|meta| meta.get("tables")).and_then(|tables| tables.as_object()) {
+            tables.extend(named.keys().cloned().map(Some));
+        }
+
+        let mut defined: HashMap<Option<String>, HashMap<String, bool>> = HashMap::new();
+
+        for table in tables {
+            if let Some(definitions) = pointer::get(root, &definitions_path(table.as_deref())).and_then(|definitions| definitions.as_object()) {
+                let fields = definitions.iter()
+                    .map(|(field, definition)| (field.clone(), definition.get("unique").and_then(|unique| unique.as_bool()).unwrap_or(false)))
+                    .collect();
+
+                defined.insert(table, fields);
+            }
+        }
+
+        for (table, fields) in &defined {
+            for (field, unique) in fields {
+                let current = self.get(table.as_deref(), field);
+
+                if current.is_none_or(|index| index.unique != *unique) {
+                    rebuilt.insert((table.clone(), field.clone()), Index::build(root, table.as_deref(), field, *unique, now)?);
+                }
+            }
+        }
+
+        for (table, fields) in &self.tables {
+            for field in fields.keys() {
+                if !defined.get(table).is_some_and(|defined| defined.contains_key(field)) {
+                    dropped.insert((table.clone(), field.clone()));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,194 @@
+//! Iterators over the keys and values of a [Database](crate::Database) or a [Tree](crate::Tree)
+//!
+//! The iterators own a snapshot of the tree that was taken when they were made,
+//! so they see a consistent view even while other threads keep writing.
+//! Prefix and range scans and pages always come out sorted by key. On a table they seek right
+//! to their first key in the keys of the table that the indexes keep in order.
+
+use std::collections::BTreeSet;
+use std::ops::{ Bound, RangeBounds };
+
+use crate::{ Error, Result, Value };
+
+/// One page of entries from a paginated scan like [Database::page](crate::Database::page)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// The entries on this page sorted by key
+    pub entries: Vec<(String, Value)>,
+
+    /// The cursor to pass as `after` to get the next page. This is [None] on the last page
+    pub next: Option<String>
+}
+
+/// The keys in `range` that start with `prefix`, in key order.
+/// This seeks right to the first of them, so the keys before it are never looked at
+pub(crate) fn scan<'k, 'r, R>(keys: &'k BTreeSet<String>, range: &R, prefix: &str) -> impl Iterator<Item = &'k String> + 'k
+where
+    R: RangeBounds<&'r str> + ?Sized
+{
+    // Every key with the prefix comes at or after the prefix itself
+    let start = match range.start_bound().map(|key| *key) {
+        Bound::Included(key) | Bound::Excluded(key) if key < prefix => Bound::Included(prefix),
+        Bound::Unbounded => Bound::Included(prefix),
+        start => start
+    };
+
+    let end = range.end_bound().map(|key| *key);
+    let prefix = prefix.to_string();
+
+    return (!is_empty(start, end)).then(|| keys.range::<str, _>((start, end)))
+        .into_iter()
+        .flatten()
+        .take_while(move |key| key.starts_with(&prefix));
+}
+
+/// Check if a range has no keys in it. [BTreeSet::range] panics on those
+fn is_empty(start: Bound<&str>, end: Bound<&str>) -> bool {
+    return match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => start >= end,
+        _ => false
+    }
+}
+
+/// Up to `limit` entries with a key that starts with `prefix` and comes after the `after` cursor.
+/// `entry` finds the value of a key or returns [None] when the key is left out, like one that has expired
+pub(crate) fn page<'v, F>(keys: &BTreeSet<String>, prefix: &str, after: Option<&str>, limit: usize, entry: F) -> Result<Page>
+where
+    F: Fn(&str) -> Option<&'v serde_json::Value>
+{
+    // A page without entries would have no cursor to go on from
+    if limit == 0 {
+        return Err(Error::InvalidQuery(String::from("the limit of a page must be at least 1")));
+    }
+
+    let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+
+    let mut entries: Vec<(String, Value)> = scan(keys, &(start, Bound::Unbounded), prefix)
+        .filter_map(|key| entry(key).map(|val| (key.clone(), Value::from(val.clone()))))
+        .take(limit.saturating_add(1))
+        .collect();
+
+    let next = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|(key, _)| key.clone())
+    } else {
+        None
+    };
+
+    return Ok(Page {
+        entries,
+        next
+    });
+}
+
+/// An iterator over the `(key, value)` pairs of a tree in key order
+pub struct Iter {
+    inner: std::vec::IntoIter<(String, serde_json::Value)>
+}
+
+impl Iter {
+    pub(crate) fn new(map: serde_json::Map<String, serde_json::Value>) -> Iter {
+        let mut entries: Vec<(String, serde_json::Value)> = map.into_iter().collect();
+
+        // The map is only sorted when serde_json is built without `preserve_order`
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        return Iter {
+            inner: entries.into_iter()
+        }
+    }
+
+    /// An iterator over a copy of entries that are already sorted
+    pub(crate) fn sorted<'a, I: Iterator<Item = (&'a String, &'a serde_json::Value)>>(entries: I) -> Iter {
+        return Iter {
+            inner: entries.map(|(key, val)| (key.clone(), val.clone())).collect::<Vec<_>>().into_iter()
+        }
+    }
+}
+
+impl Iterator for Iter {
+    type Item = (String, Value);
+
+    fn next(&mut self) -> Option<(String, Value)> {
+        return self.inner.next().map(|(key, val)| (key, Value::from(val)));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return self.inner.size_hint();
+    }
+}
+
+impl DoubleEndedIterator for Iter {
+    fn next_back(&mut self) -> Option<(String, Value)> {
+        return self.inner.next_back().map(|(key, val)| (key, Value::from(val)));
+    }
+}
+
+impl ExactSizeIterator for Iter {}
+
+/// An iterator over the keys of a tree in key order
+pub struct Keys {
+    inner: Iter
+}
+
+impl Keys {
+    pub(crate) fn new(map: serde_json::Map<String, serde_json::Value>) -> Keys {
+        return Keys {
+            inner: Iter::new(map)
+        }
+    }
+}
+
+impl Iterator for Keys {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        return self.inner.next().map(|(key, _)| key);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return self.inner.size_hint();
+    }
+}
+
+impl DoubleEndedIterator for Keys {
+    fn next_back(&mut self) -> Option<String> {
+        return self.inner.next_back().map(|(key, _)| key);
+    }
+}
+
+impl ExactSizeIterator for Keys {}
+
+/// An iterator over the values of a tree in key order
+pub struct Values {
+    inner: Iter
+}
+
+impl Values {
+    pub(crate) fn new(map: serde_json::Map<String, serde_json::Value>) -> Values {
+        return Values {
+            inner: Iter::new(map)
+        }
+    }
+}
+
+impl Iterator for Values {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        return self.inner.next().map(|(_, val)| val);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return self.inner.size_hint();
+    }
+}
+
+impl DoubleEndedIterator for Values {
+    fn next_back(&mut self) -> Option<Value> {
+        return self.inner.next_back().map(|(_, val)| val);
+    }
+}
+
+impl ExactSizeIterator for Values {}
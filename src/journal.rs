@@ -0,0 +1,142 @@
+//! The write ahead journal behind the journaled mode of [Database](crate::Database)
+//!
+//! In journaled mode every change to the main tree is appended to the [Storage](crate::Storage)
+//! as a record of json instead of rewriting the whole snapshot. For a file that is a line in `<path>.journal`.
+//! A change is a key or a [path](crate::pointer) in the main tree that is set or removed.
+//! The changes of a transaction go into one record together so they are replayed all or nothing.
+//! [Database::load](crate::Database::load) replays the journal on top of the snapshot
+//! and [Database::compact](crate::Database::compact) folds it back into the snapshot.
+
+use std::path::{ Path, PathBuf };
+
+use crate::pointer;
+use crate::{ Error, Result };
+
+/// A single change to the main tree as it is written in the journal
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Record {
+    /// Set the value at a path in the main tree
+    Set { path: Vec<String>, value: serde_json::Value },
+
+    /// Remove the value at a path from the main tree
+    Remove { path: Vec<String> }
+}
+
+impl Record {
+    /// Set a key in the main tree to a value
+    pub(crate) fn set(key: &str, value: serde_json::Value) -> Record {
+        return Record::Set { path: vec![key.to_string()], value };
+    }
+
+    /// Remove a key from the main tree
+    pub(crate) fn remove(key: &str) -> Record {
+        return Record::Remove { path: vec![key.to_string()] };
+    }
+
+    /// The path in the main tree that is changed
+    pub(crate) fn path(&self) -> &[String] {
+        return match self {
+            Record::Set { path, .. } | Record::Remove { path } => path
+        }
+    }
+
+    /// Apply the change to the main tree
+    pub(crate) fn apply(&self, root: &mut serde_json::Value) -> Result<()> {
+        match self {
+            Record::Set { path, value } => {
+                pointer::set(root, path, value.clone())?;
+            },
+
+            Record::Remove { path } => {
+                pointer::remove(root, path)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// The change that puts back everything this change is going to touch in `root`
+    /// This has to be made before the change is applied
+    pub(crate) fn inverse(&self, root: &serde_json::Value) -> Record {
+        let scope = pointer::scope(root, self.path()).to_vec();
+
+        return match pointer::get(root, &scope) {
+            Some(old) => Record::Set { path: scope, value: old.clone() },
+            None => Record::Remove { path: scope }
+        }
+    }
+
+    /// The record that goes into the journal once the change has been applied to `root`.
+    /// Replaying a record twice after a crash has to be harmless, which is not the case
+    /// for a change inside of an array (appending with `-` or removing an index twice),
+    /// so for those we write the whole array instead
+    pub(crate) fn durable(&self, root: &serde_json::Value) -> Record {
+        let scope = pointer::scope(root, self.path());
+
+        if scope.len() < self.path().len() {
+            if let Some(array) = pointer::get(root, scope).filter(|value| value.is_array()) {
+                return Record::Set { path: scope.to_vec(), value: array.clone() };
+            }
+        }
+
+        return self.clone();
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = match self {
+            Record::Set { value, .. } => serde_json::json!({ "op": "set", "value": value }),
+            Record::Remove { .. } => serde_json::json!({ "op": "remove" })
+        };
+
+        // Plain keys of the main tree are written as they are to keep the journal easy to read
+        match self.path() {
+            [key] => json["key"] = serde_json::json!(key),
+            path => json["path"] = serde_json::json!(pointer::format(path))
+        }
+
+        return json;
+    }
+
+    fn from_json(json: &serde_json::Value) -> Option<Record> {
+        let path = match json.get("key") {
+            Some(key) => vec![key.as_str()?.to_string()],
+            None => pointer::parse(json["path"].as_str()?).ok()?
+        };
+
+        return match json["op"].as_str()? {
+            "set" => Some(Record::Set { path, value: json.get("value")?.clone() }),
+            "remove" => Some(Record::Remove { path }),
+            _ => None
+        }
+    }
+}
+
+/// The path of the journal that belongs to the database at `path`
+pub(crate) fn path(path: &Path) -> PathBuf {
+    let mut journal = path.as_os_str().to_owned();
+    journal.push(".journal");
+
+    return PathBuf::from(journal);
+}
+
+/// The journal record of a change. The records of a transaction go into one record together so they are replayed all or nothing
+pub(crate) fn encode(records: &[Record]) -> Result<Vec<u8>> {
+    let json = match records {
+        [record] => record.to_json(),
+        records => serde_json::json!({ "op": "batch", "records": records.iter().map(Record::to_json).collect::<Vec<_>>() })
+    };
+
+    return Ok(serde_json::to_vec(&json)?);
+}
+
+/// The changes in a journal record
+pub(crate) fn decode(record: &[u8]) -> Result<Vec<Record>> {
+    let json: serde_json::Value = serde_json::from_slice(record)?;
+
+    let records = match json["op"].as_str() {
+        Some("batch") => json["records"].as_array().and_then(|records| records.iter().map(Record::from_json).collect()),
+        _ => Record::from_json(&json).map(|record| vec![record])
+    };
+
+    return records.ok_or_else(|| Error::Parse(serde::de::Error::custom(format!("invalid journal record {}", json))));
+}
@@ -13,15 +13,18 @@
 //! We can directly insert values into the main tree too
 //! 
 //! ```rust
+//! # use dino::*;
+//! # let path = std::env::temp_dir().join(format!("dino-doc-basic-{}.dino", std::process::id()));
+//! # let path = path.to_str().unwrap();
 //! // Create the database instance
-//! let mut db = Database::new("./basic.dino");
+//! let mut db = Database::new(path);
 //!
 //! // Load and create the database if does not exist
-//! db.load();
+//! db.load().unwrap();
 //!
 //! // Insert values in the db in the format of key, value
-//! db.insert("key-1", "value-1");
-//! db.insert("key-2", "value-2");
+//! db.insert("key-1", "value-1").unwrap();
+//! db.insert("key-2", "value-2").unwrap();
 //! ```
 //! 
 //! ## Sub Trees
@@ -30,33 +33,38 @@
 //! Now in this case making a sub tree is essential as there is a `id` key with some values
 //! 
 //! ```rust
-//! 
+//! # use dino::*;
+//! # let path = std::env::temp_dir().join(format!("dino-doc-sub-trees-{}.dino", std::process::id()));
+//! # let path = path.to_str().unwrap();
 //! // Create the database instance
-//! let mut db = Database::new("./sub_trees.dino");
+//! let mut db = Database::new(path);
 //! 
 //! // Load and create the database if does not exist
-//! db.load();
+//! db.load().unwrap();
 //! 
 //! // Create a new sub Tree in the main Tree of the db
 //! let mut data_tree = Tree::new();
 //! 
 //! // Insert the key and value in the sub tree
-//! data_tree.insert("a", "b");
+//! data_tree.insert("a", "b").unwrap();
 //! 
 //! // Insert the [data_tree] under the main tree
-//! db.insert_tree("id", data_tree);
+//! db.insert_tree("id", data_tree).unwrap();
 //! ```
 //! 
 //! ## Querying the Database
 //! ```rust
+//! # use dino::*;
+//! # let path = std::env::temp_dir().join(format!("dino-doc-querying-{}.dino", std::process::id()));
+//! # let path = path.to_str().unwrap();
 //! // Create the database instance
-//! let mut db = Database::new("./basic.dino");
+//! let mut db = Database::new(path);
 //! 
 //! // Load and create the database if does not exist
-//! db.load();
+//! db.load().unwrap();
 //!
 //! // Insert values in the db in the format of key, value
-//! db.insert("key-1", "value-1");
+//! db.insert("key-1", "value-1").unwrap();
 //! 
 //! // Print the value of `key-1`
 //! println!("The value of key: id is {}", db.find("key-1").unwrap());
@@ -74,17 +82,20 @@
 //! ```
 //! ## Basic Operations
 //! ```rust
+//! # use dino::*;
+//! # let path = std::env::temp_dir().join(format!("dino-doc-operations-{}.dino", std::process::id()));
+//! # let path = path.to_str().unwrap();
 //! // Create the database instance
-//! let mut db = Database::new("./basic.dino");
+//! let mut db = Database::new(path);
 //! 
 //! // Load and create the database if does not exist
-//! db.load();
+//! db.load().unwrap();
 //!
 //! // Insert values in the db in the format of key, value
-//! db.insert("id", "value-1");
+//! db.insert("id", "value-1").unwrap();
 //! 
 //! // Remove a key in the database with its value
-//! db.remove("id");
+//! db.remove("id").unwrap();
 //!
 //! // Now here it wont print that it exists as it does not we removed it ^^^^^
 //! if db.contains_key("id") {
@@ -95,7 +106,7 @@
 //! ```
 //! ## Using it with rocket.rs
 //! 
-//! ```rust
+//! ```rust,ignore
 //! // Simple rocket route
 //! #[get("/<id>")]
 //! // Here we add the arg `db: State<dino::Database>`
@@ -108,14 +119,14 @@
 //!         // If it exists it will return Ok(value)
 //!         Ok(value) => {
 //!             // Then we can return the value!
-//!             return value.to_string();
+//!             return format!("{}", value);
 //!         }
 //!
 //!         // If it does not exists it gives a error
 //!         Err(error) => {
 //!             // So return the error!
 //!             // You might want to handle the error too!
-//!             return error;
+//!             return error.to_string();
 //!         }
 //!    }
 //! }
@@ -125,10 +136,10 @@
 //!     let mut db = dino::Database::new("rocket.dino");
 //!
 //!     // Load and create the database if does not exist
-//!     db.load();
+//!     db.load().unwrap();
 //!
 //!     // Insert a key with a dummy value for now!
-//!     db.insert("key", "value!");
+//!     db.insert("key", "value!").unwrap();
 //!
 //!     // Ignite the rocket and mount the routes
 //!     rocket::ignite()
@@ -142,344 +153,2807 @@
 //! ```
 
 #![feature(test)]
+#![allow(clippy::needless_return)]
 extern crate test;
 
-use std::fs::{ OpenOptions, File };
-use std::io::SeekFrom;
-use std::io::prelude::*;
+use std::ops::RangeBounds;
 use std::fmt;
-use std::sync::Mutex;
+use std::sync::{ Arc, Mutex, RwLock };
+use std::sync::mpsc::Receiver;
+use std::time::{ Duration, SystemTime };
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+mod batch;
+mod compression;
+mod doc;
+mod encryption;
+mod error;
+mod format;
+mod index;
+mod iter;
+mod journal;
+mod lock;
+mod pointer;
+mod query;
+mod schema;
+mod storage;
+mod table;
+mod transaction;
+mod ttl;
+mod update;
+mod watch;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{ AsyncDatabase, AsyncTable };
+pub use batch::WriteBatch;
+pub use compression::Compression;
+pub use doc::DocId;
+pub use error::{ Error, Result };
+pub use format::Format;
+pub use iter::{ Iter, Keys, Page, Values };
+pub use query::{ Order, Query, Where };
+pub use schema::Schema;
+pub use storage::{ FileStorage, MemoryStorage, Storage };
+pub use table::Table;
+pub use transaction::Transaction;
+pub use ttl::{ Clock, ManualClock, Sweeper, SystemClock };
+pub use update::Updated;
+pub use watch::{ Event, EventKind };
+use encryption::{ Cipher, Generation };
+use index::Indexes;
+use journal::Record;
+use schema::Patterns;
+use watch::{ Before, Watchers };
 
 /// The main struct of Dino.
 /// The [Database] struct is responsible for creating the storage instance
 /// that will store this database's documents, managing the database
 /// tables as well as providing access to the default table.
-
 pub struct Database {
     /// The path of the file in a [String] format
     pub path: String,
 
-    /// The File object that we get when we open the database file
-    file: Mutex<Option<File>>,
+    /// Everything that is loaded from the disk or [None] before [Database::load].
+    /// Readers share it and a writer has it to itself until the change is saved
+    state: RwLock<Option<State>>,
+
+    /// How many records the journal may hold before it is folded into the file
+    /// This is [None] when the database is not in journaled mode
+    journal_threshold: Option<usize>,
+
+    /// Changes are only saved when [Database::flush] is called
+    manual_flush: bool,
+
+    /// The database is opened read only with a shared lock
+    read_only: bool,
+
+    /// How long [Database::load] waits for another process to let go of the lock
+    lock_timeout: Option<Duration>,
+
+    /// Where the data is read from and saved to
+    storage: Mutex<Box<dyn Storage>>,
 
-    /// The raw data in the file
-    data: Mutex<Option<String>>,
-    
+    /// How the snapshot is encoded when it is saved
+    format: Format,
+
+    /// How the snapshot is compressed when it is saved
+    compression: Compression,
+
+    /// The cipher that seals the snapshot and the journal or [None] when the database is not encrypted
+    cipher: Option<Cipher>,
+
+    /// Where the time comes from for keys that expire
+    clock: Arc<dyn Clock>,
+
+    /// The channels and callbacks that are told about changes
+    watchers: Watchers
+}
+
+/// The state of a loaded [Database]
+struct State {
     /// The json value of the file. Dino uses Json in backend to parse the database
-    json: Mutex<Option<serde_json::Value>>
+    json: serde_json::Value,
+
+    /// The amount of records in the journal when the database is in journaled mode
+    journal: Option<usize>,
+
+    /// The generation of the snapshot that the records in the journal are sealed after
+    generation: Generation,
+
+    /// The changes that are not flushed yet in manual flush mode or [None] when everything is saved.
+    /// The records themselves are only kept for the journal
+    unflushed: Option<Vec<Record>>,
+
+    /// The secondary indexes of the tables. They are built on load and kept up to date with the main tree
+    indexes: Indexes,
+
+    /// The compiled patterns of the schemas
+    patterns: Patterns
 }
 
 impl Database {
     /// Create a new instance of the [Database]
     pub fn new(path: &str) -> Database {
+        let mut db = Database::with_storage(FileStorage::new(path));
+        db.path = String::from(path);
+
+        return db;
+    }
+
+    /// Create a new instance of the [Database] that keeps its data in `storage`, like a [MemoryStorage] in tests
+    pub fn with_storage<S: Storage + 'static>(storage: S) -> Database {
         return Database {
-            path: String::from(path),
-            file: Mutex::new(None),
-            data: Mutex::new(None),
-            json: Mutex::new(None)
+            path: String::new(),
+            state: RwLock::new(None),
+            journal_threshold: None,
+            manual_flush: false,
+            read_only: false,
+            lock_timeout: None,
+            storage: Mutex::new(Box::new(storage)),
+            format: Format::Json,
+            compression: Compression::None,
+            cipher: None,
+            clock: Arc::new(SystemClock),
+            watchers: Watchers::default()
+        }
+    }
+
+    /// Create a database that lives in memory only. It is loaded already and never creates a file,
+    /// until [Database::persist_to] turns it into a normal database file
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// let db = Database::in_memory();
+    ///
+    /// db.insert("key", "value").unwrap();
+    ///
+    /// assert_eq!(db.get::<String>("key").unwrap(), "value");
+    /// ```
+    pub fn in_memory() -> Database {
+        let db = Database::with_storage(MemoryStorage::new());
+
+        *db.state.write().unwrap() = Some(State {
+            json: serde_json::json!({}),
+            journal: None,
+            generation: Generation::default(),
+            unflushed: None,
+            indexes: Indexes::default(),
+            patterns: Patterns::default()
+        });
+
+        return db;
+    }
+
+    /// Save everything to the database file at `path` and keep using that file from now on,
+    /// like a database that was opened with [Database::new]. This works for any database,
+    /// but it is meant for one that was made with [Database::in_memory]
+    pub fn persist_to(&mut self, path: &str) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        // The journal at the path is cleared, so there are no records of an earlier generation there
+        let (snapshot, generation) = self.read(|root| self.snapshot(root, Generation::default()))??;
+        let mut storage = FileStorage::new(path);
+
+        storage.lock(false, self.lock_timeout)?;
+        storage.write_snapshot(&snapshot)?;
+
+        // A journal of an old database at the path would be replayed on top of the new snapshot
+        storage.clear_records()?;
+
+        let state = self.state.get_mut().unwrap().as_mut().ok_or(Error::NotLoaded)?;
+
+        if let Some(journal) = state.journal.as_mut() {
+            *journal = 0;
+        }
+
+        state.generation = generation;
+
+        // The changes that were not flushed are in the file now
+        state.unflushed = None;
+        self.watchers.release();
+
+        *self.storage.get_mut().unwrap() = Box::new(storage);
+        self.path = String::from(path);
+
+        self.watchers.deliver();
+
+        return Ok(());
+    }
+
+    /// Create a new instance of the [Database] that is encrypted with `key`. Call [Database::load] next.
+    /// The whole file and its journal are sealed with XChaCha20-Poly1305, so loading it with
+    /// another key or after it was changed returns [Error::Decryption]
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let path = std::env::temp_dir().join(format!("dino-doc-encrypted-{}.dino", std::process::id()));
+    /// # let path = path.to_str().unwrap();
+    /// let mut db = Database::open_encrypted(path, &[7; 32]);
+    /// db.load().unwrap();
+    /// db.insert("ssn", "123-45-6789").unwrap();
+    /// drop(db);
+    ///
+    /// let mut wrong = Database::open_encrypted(path, &[8; 32]);
+    /// assert!(matches!(wrong.load(), Err(Error::Decryption(_))));
+    /// ```
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: &str, key: &[u8; 32]) -> Database {
+        let mut db = Database::new(path);
+        db.enable_encryption(key);
+
+        return db;
+    }
+
+    /// Encrypt the database with `key`, like one that was made with [Database::open_encrypted].
+    /// Call this before [Database::load]. This works with any storage
+    #[cfg(feature = "encryption")]
+    pub fn enable_encryption(&mut self, key: &[u8; 32]) {
+        self.cipher = Some(Cipher::new(key));
+    }
+
+    /// Save the database encrypted with `new_key` and use that key from now on.
+    /// This encrypts a database that was not encrypted before too.
+    /// The journal is folded in with the old key first, so a crash leaves a database that opens with one of the keys
+    #[cfg(feature = "encryption")]
+    pub fn rekey(&mut self, new_key: &[u8; 32]) -> Result<()> {
+        self.compact()?;
+
+        let old = self.cipher.replace(Cipher::new(new_key));
+
+        if let Err(error) = self.compact() {
+            self.cipher = old;
+
+            return Err(error);
         }
+
+        return Ok(());
+    }
+
+    /// Turn on the journaled mode. Call this before [Database::load]
+    /// Instead of rewriting the whole file on every change, changes are appended to `<path>.journal`
+    /// and folded back into the file once the journal holds `compact_threshold` records.
+    /// With a `compact_threshold` of `0` the journal is only folded when you call [Database::compact]
+    pub fn enable_journal(&mut self, compact_threshold: usize) {
+        self.journal_threshold = Some(compact_threshold);
+    }
+
+    /// Turn on the manual flush mode. Call this before [Database::load]
+    /// Changes only mark the database as dirty and are saved when [Database::flush] is called.
+    /// Dropping the database flushes it too, but errors are lost there so flush yourself when they matter
+    pub fn enable_manual_flush(&mut self) {
+        self.manual_flush = true;
+    }
+
+    /// Open the database read only. Call this before [Database::load]
+    /// Any number of processes can open a database read only at the same time but none can open it read write.
+    /// The file is not created when it does not exist and every change returns [Error::ReadOnly]
+    pub fn enable_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// Wait up to `timeout` in [Database::load] when another process has the database locked
+    /// Without a timeout [Database::load] returns [Error::Locked] right away
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = Some(timeout);
+    }
+
+    /// Save the snapshot in another [Format] from now on. A database in any format is loaded no
+    /// matter which format is set, so an existing database switches over the next time it is saved
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// let mut db = Database::in_memory();
+    /// db.set_format(Format::Json);
+    /// ```
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Compress the snapshot from now on. A compressed database is detected and decompressed on
+    /// load no matter which compression is set, so plain files keep working
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Use another clock for the keys that expire, like a [ManualClock] in tests
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
     }
 
     /// Load the database from the file and initialize variables
-    pub fn load(&mut self) {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.path.to_string())
-            .unwrap();
-        
-        let mut buf = String::new();
+    /// This takes a lock on the database so no other process can open it at the same time,
+    /// or only read only ones when the database itself is read only
+    pub fn load(&mut self) -> Result<()> {
+        let storage = self.storage.get_mut().unwrap();
+        storage.lock(self.read_only, self.lock_timeout)?;
+
+        let (mut json, generations) = match storage.read_snapshot()? {
+            Some(snapshot) => {
+                let (snapshot, generations) = encryption::open_snapshot(self.cipher.as_ref(), snapshot)?;
+
+                (format::decode(&compression::decompress(snapshot)?)?, generations)
+            },
+
+            None => (serde_json::json!({}), Default::default())
+        };
+
+        if !json.is_object() {
+            return Err(Error::mismatch("tree", &json));
+        }
+
+        // Replay the changes that have not been folded into the snapshot yet
+        let (records, generation) = encryption::open_records(self.cipher.as_ref(), &generations, &storage.read_records()?)?;
 
-        file.read_to_string(&mut buf).unwrap();
+        for record in &records {
+            for change in journal::decode(record)? {
+                change.apply(&mut json)?;
+            }
+        }
+
+        let journal = if self.read_only || (self.journal_threshold.is_none() && records.is_empty()) { None } else { Some(records.len()) };
+        let replayed = journal.is_some();
+
+        *self.state.get_mut().unwrap() = Some(State {
+            indexes: Indexes::load(&json, ttl::millis(self.clock.now()))?,
+            json,
+            journal,
+            generation,
+            unflushed: None,
+            patterns: Patterns::default()
+        });
 
-        let json = serde_json::from_str(if buf == "" { "{}" } else { buf.as_str() }).unwrap();
+        // A journal left behind by a journaled session is folded in and cleared when the journal is off
+        if self.journal_threshold.is_none() && replayed {
+            self.compact()?;
 
-        self.file = Mutex::new(Some(file));
-        self.data = Mutex::new(Some(buf));
-        self.json = Mutex::new(Some(json));
+            if let Some(state) = self.state.get_mut().unwrap().as_mut() {
+                state.journal = None;
+            }
+        }
+
+        return Ok(());
     }
 
     /// Insert a key with a subtree in the database
-    pub fn insert_tree(&self, key: &str, value: Tree) {
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::from_str(value.children.unwrap().to_string().as_str()).unwrap());
-        
-        self.save_data();
+    pub fn insert_tree(&self, key: &str, value: Tree) -> Result<()> {
+        return self.default_table().insert_tree(key, value);
     }
 
     /// Insert a key and a value in the database
-    pub fn insert(&self, key: &str, value: &str) {        
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
-
-        self.save_data();
+    /// The value can be anything that implements [Serialize], like your own structs and enums
+    pub fn insert<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        return self.default_table().insert(key, value);
     }
 
     /// Insert a key and a value in the database
-    pub fn insert_number(&self, key: &str, value: usize) {        
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    pub fn insert_number(&self, key: &str, value: usize) -> Result<()> {
+        return self.default_table().insert(key, &value);
+    }
 
-        self.save_data();
+    pub fn insert_array(&self, key: &str, value: Vec<&str>) -> Result<()> {
+        return self.default_table().insert(key, &value);
     }
 
-    pub fn insert_array(&self, key: &str, value: Vec<&str>) {
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    pub fn insert_bool(&self, key: &str, value: bool) -> Result<()> {
+        return self.default_table().insert(key, &value);
+    }
 
-        self.save_data();
+    /// Remove a key in the database with its value
+    pub fn remove(&self, key: &str) -> Result<()> {
+        return self.default_table().remove(key);
     }
 
-    pub fn insert_bool(&self, key: &str, value: bool) {
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    /// Set the value at a path like `users/alice/age` in the database
+    /// Trees on the way that do not exist yet are created. See [Database::get_path] for the path syntax
+    pub fn set_path<T: Serialize + ?Sized>(&self, path: &str, value: &T) -> Result<()> {
+        return self.default_table().set_path(path, value);
+    }
 
-        self.save_data();
+    /// Remove the value at a path like `users/alice/age` in the database
+    pub fn remove_path(&self, path: &str) -> Result<()> {
+        return self.default_table().remove_path(path);
     }
 
-    /// Remove a key in the database with its value
-    pub fn remove(&self, key: &str) {
-        self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().remove(key);
-        
-        self.save_data();
+    /// Insert a document under the next id of the database and return the id
+    /// See [Table::insert_doc] for the details
+    pub fn insert_doc<T: Serialize + ?Sized>(&self, doc: &T) -> Result<DocId> {
+        return self.default_table().insert_doc(doc);
     }
 
-    /// Private function but is very important. 
-    /// This truncates the db before we write the json code again
-    /// And saves the data to the file
-    fn save_data(&self) {
-        self.file.lock().unwrap().as_ref().unwrap().set_len(0).unwrap();
-        self.file.lock().unwrap().as_ref().unwrap().seek(SeekFrom::Start(0)).unwrap();
+    /// Get the document with the id `id` as any type that implements [DeserializeOwned]
+    pub fn get_doc<T: DeserializeOwned>(&self, id: DocId) -> Result<T> {
+        return self.default_table().get_doc(id);
+    }
 
-        self.file.lock().unwrap().as_mut().unwrap().write(serde_json::to_string_pretty(&self.json.lock().unwrap().as_ref().unwrap()).unwrap().as_bytes()).expect("Cannot write to the database!");
+    /// Find the document with the id `id`
+    pub fn find_doc(&self, id: DocId) -> Result<Value> {
+        return self.default_table().find_doc(id);
     }
 
-    /// Find a value in the db
-    pub fn find(&self, key: &str) -> Result<Value, String> {
-        let json = &self.json.lock().unwrap();
-        let val = &json.as_ref().unwrap()[key];
+    /// Replace the document with the id `id`. Returns [Error::NotFound] when there is no such document
+    pub fn update_doc<T: Serialize + ?Sized>(&self, id: DocId, doc: &T) -> Result<()> {
+        return self.default_table().update_doc(id, doc);
+    }
 
-        if val == &serde_json::Value::Null {
-            return Err(format!("The key `{}` does not exist in the database. You might want to create this or handle the error!", key))
-        }
+    /// Remove the document with the id `id`
+    pub fn remove_doc(&self, id: DocId) -> Result<()> {
+        return self.default_table().remove_doc(id);
+    }
 
-        return Ok(Value::from(serde_json::to_string_pretty(&val).unwrap().as_str()));
+    /// Change every entry that matches `filter` with mongo style operators. See [Table::update]
+    pub fn update(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.default_table().update(filter, update);
     }
 
-    /// Check if the key exists in the database
-    pub fn contains_key(&self, key: &str) -> bool {
-        return self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().contains_key(key);
+    /// Change every entry that matches `filter` or insert a new document when nothing matches. See [Table::upsert]
+    pub fn upsert(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.default_table().upsert(filter, update);
     }
 
-    /// Return the length of items that are in the main tree
-    pub fn len(&self) -> usize {
-        return self.json.lock().unwrap().as_mut().unwrap().as_object_mut().unwrap().len();
+    /// Index a field of the entries of the database so [Database::find_by] does not have to look at every entry.
+    /// See [Table::create_index]
+    pub fn create_index(&self, field: &str) -> Result<()> {
+        return self.default_table().create_index(field);
     }
-}
 
-/// The struct that allows you to create sub trees in the main tree in the database
-/// Sub trees do not auto insert in the main tree of the database
-/// You can do that by doing
-/// # Example
-/// ```rust
-/// // Create the database instance
-/// let mut db = Database::new("./hello.dino");
-/// 
-/// // Load and create the database if does not exist
-/// db.load();
-///
-/// // Create a new sub Tree in the main Tree of the db
-/// let mut data_tree = Tree::new();
-///
-/// // Insert the [data_tree] under the main tree
-/// db.insert_tree("id", data_tree);
-/// ```
-/// Where the key always need to be a [String]
+    /// Index a field that no two entries may have the same value in. See [Table::create_unique_index]
+    pub fn create_unique_index(&self, field: &str) -> Result<()> {
+        return self.default_table().create_unique_index(field);
+    }
 
-#[derive(Debug)]
-pub struct Tree {
-    children: Option<serde_json::Value>
-}
+    /// Remove the index of a field
+    pub fn drop_index(&self, field: &str) -> Result<()> {
+        return self.default_table().drop_index(field);
+    }
 
-impl Tree {
-    /// Create a new sub tree
-    pub fn new() -> Tree {
-        return Tree {
-            children: serde_json::from_str("{}").unwrap()
-        }
+    /// Return the fields that have an index sorted by name
+    pub fn indexes(&self) -> Vec<String> {
+        return self.default_table().indexes();
     }
 
-    /// Create a new Tree from String value
-    pub fn from(value: &str) -> Tree {
-        return Tree {
-            children: serde_json::from_str(value).unwrap()
-        }
+    /// Return the `(key, value)` pairs of the entries with `value` in `field`, sorted by key. See [Table::find_by]
+    pub fn find_by<T: Serialize + ?Sized>(&self, field: &str, value: &T) -> Result<Vec<(String, Value)>> {
+        return self.default_table().find_by(field, value);
     }
 
-    /// Insert data with [String] value type in the sub tree
-    pub fn insert(&mut self, key: &str, value: &str) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::Value::String(value.to_string()));
+    /// Check every entry of the database against `schema` from now on. See [Table::set_schema]
+    pub fn set_schema(&self, schema: Schema) -> Result<()> {
+        return self.default_table().set_schema(schema);
     }
 
-    /// Insert data with [usize] value type in the sub tree
-    pub fn insert_number(&mut self, key: &str, value: usize) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    /// Stop checking the entries of the database against a schema
+    pub fn remove_schema(&self) -> Result<()> {
+        return self.default_table().remove_schema();
     }
 
-    pub fn insert_array(&mut self, key: &str, value: Vec<&str>) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    /// Return the schema of the database if it has one
+    pub fn schema(&self) -> Option<Schema> {
+        return self.default_table().schema();
     }
 
-    pub fn insert_bool(&mut self, key: &str, value: bool) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    /// Insert a key and a value that expires after `ttl`. See [Table::insert_with_ttl]
+    pub fn insert_with_ttl<T: Serialize + ?Sized>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        return self.default_table().insert_with_ttl(key, value, ttl);
     }
 
-    /// Return the length of items that are in the sub tree
-    pub fn len(&mut self) -> usize {
-        return self.children.as_mut().unwrap().as_object_mut().unwrap().len();
+    /// Let a key expire at `at`. See [Table::expire]
+    pub fn expire(&self, key: &str, at: SystemTime) -> Result<()> {
+        return self.default_table().expire(key, at);
     }
 
-    /// Remove a key in the sub tree in the database with its value
-    pub fn remove(&mut self, key: &str) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().remove(key);
+    /// Return how long a key has left before it expires or [None] when it does not expire
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        return self.default_table().ttl(key);
     }
 
-    /// Find a value in the sub tree in the database
-    pub fn find(&self, key: &str) -> Result<Value, String> {
-        let val = &self.children.as_ref().unwrap()[key];
+    /// Remove every key of every table that has expired and return how many were removed
+    pub fn purge_expired(&self) -> Result<usize> {
+        let mut purged = 0;
+        let now = ttl::millis(self.clock.now());
 
-        if val == &serde_json::Value::Null {
-            return Err(format!("The key `{}` does not exist in the database. You might want to create this or handle the error!", key))
-        }
+        self.change_with(|root| {
+            let records = ttl::purge(root, now);
+            purged = records.iter().filter(|record| !record.path().starts_with(&[doc::META.to_string()])).count();
 
-        return Ok(Value::from(serde_json::to_string_pretty(val).unwrap().as_str()));
+            return Ok(records);
+        })?;
+
+        return Ok(purged);
     }
 
-    /// Check if the key exists in the sub tree of the main database
-    pub fn contains_key(&mut self, key: &str) -> bool {
-        return self.children.as_mut().unwrap().as_object_mut().unwrap().contains_key(key);
+    /// Start a thread that calls [Database::purge_expired] every `interval`.
+    /// It stops when the returned [Sweeper] is dropped or when the database goes away
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let db = Arc::new(Database::in_memory());
+    /// let sweeper = db.start_sweeper(Duration::from_secs(60));
+    /// ```
+    pub fn start_sweeper(self: &Arc<Self>, interval: Duration) -> Sweeper {
+        return Sweeper::start(Arc::downgrade(self), interval);
     }
 
-    /// Insert a key with a subtree in the subtree!
-    pub fn insert_tree(&mut self, key: &str, value: Tree) {
-        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::from_str(value.children.unwrap().to_string().as_str()).unwrap());
+    /// Return a channel that gets an [Event] for every change to a key of the default table that starts with `prefix`.
+    /// Use [Table::subscribe] for the other tables
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let path = std::env::temp_dir().join(format!("dino-doc-subscribe-{}.dino", std::process::id()));
+    /// # let mut db = Database::new(path.to_str().unwrap());
+    /// # db.load().unwrap();
+    /// let events = db.subscribe("user:");
+    ///
+    /// db.insert("user:alice", "Alice").unwrap();
+    /// db.insert("order:1", &42).unwrap();
+    ///
+    /// let event = events.try_recv().unwrap();
+    ///
+    /// assert_eq!(event.key, "user:alice");
+    /// assert!(events.try_recv().is_err());
+    /// ```
+    pub fn subscribe(&self, prefix: &str) -> Receiver<Event> {
+        return self.default_table().subscribe(prefix);
     }
-}
 
-/// This struct is returned when you find something in the database.
-/// Value also impls fmt::Display
+    /// Call `callback` with every [Event] of every table, on the thread that made the change after it is saved.
+    /// The events of one change come one after the other and before the events of the next change.
+    /// A change that the callback makes is delivered once the callback returns
+    pub fn on_change<F: Fn(&Event) + Send + Sync + 'static>(&self, callback: F) {
+        self.watchers.on_change(Arc::new(callback));
+    }
 
-#[derive(Debug)]
-pub struct Value {
-    val: serde_json::Value
-}
+    /// Start a [Query] over the entries of the default table
+    pub fn query(&self) -> Query<'_> {
+        return self.default_table().query();
+    }
 
-/// Impl for Value struct
-impl Value {
-    /// Value from &str
-    fn from(value: &str) -> Value{
-        return Value {
-            val: serde_json::from_str(value).unwrap()
-        }
+    /// The default table. This is the main tree of the file that the methods of [Database] work on
+    pub fn default_table(&self) -> Table<'_> {
+        return Table::new(self, None);
     }
 
-    /// Return the string value
-    pub fn to_string(&self) -> String {
-        return self.val.as_str().unwrap().to_string();
+    /// The table called `name`. The table is created by the first insert into it
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// db.table("orders").insert("order-1", &42).unwrap();
+    /// db.table("users").insert("alice", &"Alice").unwrap();
+    ///
+    /// assert_eq!(db.tables(), vec!["orders", "users"]);
+    /// ```
+    pub fn table(&self, name: &str) -> Table<'_> {
+        return Table::new(self, Some(name));
     }
 
-    /// Return the number value
-    pub fn to_number(&self) -> usize {
-        return serde_json::to_string(&self.val).unwrap().parse::<usize>().unwrap();
+    /// Return the names of the named tables in the database sorted by name
+    /// A database that is not loaded yet has no tables
+    pub fn tables(&self) -> Vec<String> {
+        let mut names = self.read(|root| {
+            return root.get(table::TABLES)
+                .and_then(|tables| tables.as_object())
+                .map_or_else(Vec::new, |tables| tables.keys().cloned().collect::<Vec<String>>());
+        }).unwrap_or_default();
+
+        names.sort();
+
+        return names;
     }
 
-    /// Return the Tree value
-    pub fn to_tree(&self) -> Tree {
-        return Tree::from(serde_json::to_string(&self.val).unwrap().as_str());
+    /// Remove the table called `name` with everything in it
+    /// Dropping a table that does not exist does nothing
+    pub fn drop_table(&self, name: &str) -> Result<()> {
+        return self.change_all(&[
+            Record::Remove { path: vec![table::TABLES.to_string(), name.to_string()] },
+            Record::Remove { path: doc::meta_path(Some(name)) }
+        ]);
     }
 
-    /// Return the Array value
-    pub fn to_vec(&self) -> Vec<String> {
-        let vector: Vec<String> = serde_json::from_str(serde_json::to_string(&self.val).unwrap().as_str()).unwrap();
+    /// Rename the table called `from` to `to`
+    /// Returns [Error::NotFound] when there is no table `from` and [Error::AlreadyExists] when there is a table `to` already
+    pub fn rename_table(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = vec![table::TABLES.to_string(), from.to_string()];
+        let to_path = vec![table::TABLES.to_string(), to.to_string()];
 
-        return vector;
-    }
+        return self.transaction(|tx| {
+            let value = match pointer::get(tx.root(), &from_path) {
+                Some(value) => value.clone(),
+                None => return Err(Error::NotFound(from.to_string()))
+            };
 
-    /// Return the bool value
-    pub fn to_bool(&self) -> bool {
-        return serde_json::to_string(&self.val).unwrap().parse::<bool>().unwrap();
+            if pointer::get(tx.root(), &to_path).is_some() {
+                return Err(Error::AlreadyExists(to.to_string()));
+            }
+
+            tx.change(Record::Remove { path: from_path.clone() })?;
+            tx.change(Record::Set { path: to_path.clone(), value })?;
+
+            // The document ids go along with the table
+            if let Some(meta) = pointer::get(tx.root(), &doc::meta_path(Some(from))).cloned() {
+                tx.change(Record::Remove { path: doc::meta_path(Some(from)) })?;
+                tx.change(Record::Set { path: doc::meta_path(Some(to)), value: meta })?;
+            }
+
+            return Ok(());
+        });
     }
 
-    pub fn to_json(&self) -> &serde_json::Value {
-        return &self.val;
-    } 
-}
+    /// Run `f` as a transaction. Everything it changes through the [Transaction] is saved in one go
+    /// when it returns [Ok] and thrown away when it returns [Err] or panics.
+    /// The database is locked while `f` runs so use the [Transaction] and not the database inside of it
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// # db.set_path("alice/balance", &10).unwrap();
+    /// # db.set_path("bob/balance", &0).unwrap();
+    /// // Move 5 from the balance of alice to the balance of bob
+    /// db.transaction(|tx| {
+    ///     let alice: u32 = tx.get_path("alice/balance")?;
+    ///     let bob: u32 = tx.get_path("bob/balance")?;
+    ///
+    ///     tx.set_path("alice/balance", &(alice - 5))?;
+    ///     tx.set_path("bob/balance", &(bob + 5))?;
+    ///
+    ///     return Ok::<_, Error>(());
+    /// }).unwrap();
+    /// ```
+    pub fn transaction<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        E: From<Error>,
+        F: FnOnce(&mut Transaction) -> std::result::Result<T, E>
+    {
+        if self.read_only {
+            return Err(Error::ReadOnly.into());
+        }
 
-/// impl Display for Value
-/// So we can print the Value to the screen
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.val {
-            serde_json::Value::Null => {
-                write!(f, "")
-            },
+        let mut guard = self.state.write().unwrap();
+        let state = guard.as_mut().ok_or(Error::NotLoaded)?;
+        let now = ttl::millis(self.clock.now());
+        let mut tx = Transaction::new(state.json.clone(), now);
 
-            serde_json::Value::Bool(ref v) => {
-                write!(f, "{}", v)
-            },
+        // Catch a panic so it does not poison the lock. Nothing was changed yet so we just let it go on
+        let result = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut tx))) {
+            Ok(result) => result?,
 
-            serde_json::Value::String(ref v) => {
-                write!(f, "{}", v)
-            },
+            Err(panic) => {
+                drop(guard);
 
-            serde_json::Value::Number(ref v) => {
-                write!(f, "{}", v)
-            },
+                std::panic::resume_unwind(panic);
+            }
+        };
 
-            serde_json::Value::Array(ref v) => {
-                write!(f, "{}", serde_json::to_string_pretty(v).unwrap())
-            },
+        let (root, records) = tx.finish();
 
-            serde_json::Value::Object(ref v) => {
-                write!(f, "{}", serde_json::to_string_pretty(v).unwrap())
-            },
+        schema::check_changes(&root, &records, &mut state.patterns)?;
+        state.indexes.refresh(&root, &records, now)?;
+
+        let old = std::mem::replace(&mut state.json, root);
+
+        if let Err(error) = self.persist(state, &records) {
+            state.json = old;
+            let _ = state.indexes.refresh(&state.json, &records, now);
+
+            return Err(error.into());
         }
+
+        if self.watchers.is_watched() {
+            self.notify(Before::take(&old, &records).events(&state.json));
+        }
+
+        drop(guard);
+        self.watchers.deliver();
+
+        return Ok(result);
     }
-}
 
-/// impl Display for Tree
-/// So we can print the tree to the display
-impl fmt::Display for Tree {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "{}", serde_json::to_string_pretty(&self.children).unwrap());
+    /// Fold the journal back into the database file
+    /// When the database is not in journaled mode the file is always up to date and this just rewrites it
+    /// This also flushes the changes of the manual flush mode
+    pub fn compact(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let mut guard = self.state.write().unwrap();
+        let state = guard.as_mut().ok_or(Error::NotLoaded)?;
+
+        let generation = self.save_data(&state.json, state.generation)?;
+
+        // The records stay sealed under the old generation until they are gone
+        if let Some(journal) = state.journal.as_mut() {
+            self.storage.lock().unwrap().clear_records()?;
+            *journal = 0;
+        }
+
+        state.generation = generation;
+
+        state.unflushed = None;
+        self.watchers.release();
+
+        drop(guard);
+        self.watchers.deliver();
+
+        return Ok(());
     }
-}
 
-/// impl Display for database
-/// So we can print the whole tree to the display
-impl fmt::Display for Database {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "{}", serde_json::to_string_pretty(&self.json).unwrap());
+    /// Apply all of the changes in a [WriteBatch] under one lock and save them in one go
+    /// If one of them fails none of them are applied
+    pub fn apply_batch(&self, batch: WriteBatch) -> Result<()> {
+        for record in &batch.records {
+            table::check_default(record.path())?;
+        }
+
+        return self.change_all(&batch.records);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Save the changes that were made in manual flush mode
+    /// This does nothing when there are no changes to save
+    pub fn flush(&self) -> Result<()> {
+        let mut guard = self.state.write().unwrap();
+        let state = guard.as_mut().ok_or(Error::NotLoaded)?;
 
-    #[bench]
-    fn create_speed(b: &mut test::Bencher) {
-        b.iter(|| {
-            let mut db = Database::new("create_speed.dino");
+        if let Some(records) = state.unflushed.take() {
+            if let Err(error) = self.write(state, &records) {
+                state.unflushed = Some(records);
 
-            db.load();
-        });
+                return Err(error);
+            }
+        }
+
+        self.watchers.release();
+
+        drop(guard);
+        self.watchers.deliver();
+
+        return Ok(());
     }
 
-    #[bench]
-    fn insert_speed(b: &mut test::Bencher) {
-        b.iter(|| {
-            let mut db = Database::new("basic_operations.dino");
+    /// Check if there are changes that are not flushed yet in manual flush mode
+    pub fn is_dirty(&self) -> bool {
+        return self.state.read().unwrap().as_ref().is_some_and(|state| state.unflushed.is_some());
+    }
 
-            db.load();
+    /// Apply a change to the main tree and persist it
+    pub(crate) fn change(&self, record: Record) -> Result<()> {
+        return self.change_all(std::slice::from_ref(&record));
+    }
 
-            db.insert("foo", "bar");
-        });
+    /// Apply changes to the main tree and persist them.
+    fn change_all(&self, records: &[Record]) -> Result<()> {
+        return self.change_with(|_| Ok(records.to_vec()));
     }
-}
\ No newline at end of file
+
+    /// Apply the changes that `f` makes up from the main tree and persist them.
+    /// The json lock is held while persisting so the journal sees the changes in the same order as the main tree.
+    /// If a change or persisting fails everything is undone so the main tree never runs ahead of the disk
+    pub(crate) fn change_with<F: FnOnce(&serde_json::Value) -> Result<Vec<Record>>>(&self, f: F) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let mut guard = self.state.write().unwrap();
+        let state = guard.as_mut().ok_or(Error::NotLoaded)?;
+
+        let now = ttl::millis(self.clock.now());
+        let mut pending = f(&state.json)?;
+        let before = if self.watchers.is_watched() { Some(Before::take(&state.json, &pending)) } else { None };
+
+        pending.reverse();
+
+        let mut applied = Vec::with_capacity(pending.len());
+        let mut inverses = Vec::with_capacity(pending.len());
+        let mut durable = Vec::with_capacity(pending.len());
+
+        let mut result = Ok(());
+
+        while let Some(record) = pending.pop() {
+            let root = &mut state.json;
+
+            // A key that is replaced or removed as a whole does not expire anymore
+            if let Some(forget) = ttl::forget(root, &record) {
+                pending.push(forget);
+            }
+
+            let inverse = record.inverse(root);
+
+            result = record.apply(root);
+
+            if result.is_err() {
+                break;
+            }
+
+            inverses.push(inverse);
+            durable.push(record.durable(root));
+            applied.push(record);
+        }
+
+        if result.is_ok() {
+            result = schema::check_changes(&state.json, &applied, &mut state.patterns);
+        }
+
+        let indexed = result.is_ok();
+
+        if indexed {
+            result = state.indexes.refresh(&state.json, &applied, now);
+        }
+
+        if result.is_ok() {
+            result = self.persist(state, &durable);
+        }
+
+        if result.is_err() {
+            // Apply every inverse even if one fails, so the tree goes back as far as it can and the original error is returned
+            for inverse in inverses.iter().rev() {
+                let _ = inverse.apply(&mut state.json);
+            }
+
+            if indexed {
+                let _ = state.indexes.refresh(&state.json, &applied, now);
+            }
+
+            return result;
+        }
+
+        if let Some(before) = before {
+            self.notify(before.events(&state.json));
+        }
+
+        drop(guard);
+        self.watchers.deliver();
+
+        return Ok(());
+    }
+
+    /// Hand the events of a saved change to the watchers. In manual flush mode they wait for [Database::flush]
+    fn notify(&self, events: Vec<Event>) {
+        if self.manual_flush {
+            self.watchers.hold(events);
+        } else {
+            self.watchers.push(events);
+        }
+    }
+
+    /// Persist changes that were already applied to the main tree
+    /// In manual flush mode they are only remembered for [Database::flush]
+    fn persist(&self, state: &mut State, records: &[Record]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        if self.manual_flush {
+            let unflushed = state.unflushed.get_or_insert_with(Vec::new);
+
+            if self.journal_threshold.is_some() {
+                unflushed.extend_from_slice(records);
+            }
+
+            return Ok(());
+        }
+
+        return self.write(state, records);
+    }
+
+    /// Write changes to the journal or save the whole main tree when the journal is off
+    fn write(&self, state: &mut State, records: &[Record]) -> Result<()> {
+        match state.journal.as_mut() {
+            Some(journal) => {
+                if records.is_empty() {
+                    return Ok(());
+                }
+
+                let record = encryption::seal_record(self.cipher.as_ref(), state.generation, *journal, journal::encode(records)?)?;
+
+                self.storage.lock().unwrap().append_record(&record)?;
+                *journal += 1;
+
+                // The change is durable once it is in the journal so a failed fold is not an error.
+                // The records are replayed just fine and we try again on the next change
+                if let Some(threshold) = self.journal_threshold {
+                    if threshold > 0 && *journal >= threshold {
+                        if let Ok(generation) = self.save_data(&state.json, state.generation) {
+                            if self.storage.lock().unwrap().clear_records().is_ok() {
+                                *journal = 0;
+                                state.generation = generation;
+                            }
+                        }
+                    }
+                }
+            },
+
+            None => {
+                state.generation = self.save_data(&state.json, state.generation)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Private function but is very important.
+    /// This hands the whole main tree to the storage as the new snapshot.
+    /// The caller holds the state for writing so two writers cannot save at the same time.
+    /// Returns the generation of the new snapshot. `previous` is the one the journal is sealed under
+    fn save_data(&self, json: &serde_json::Value, previous: Generation) -> Result<Generation> {
+        let (data, generation) = self.snapshot(json, previous)?;
+        self.storage.lock().unwrap().write_snapshot(&data)?;
+
+        return Ok(generation);
+    }
+
+    /// The main tree in the format, with the compression and sealed with the key of the database
+    fn snapshot(&self, json: &serde_json::Value, previous: Generation) -> Result<(Vec<u8>, Generation)> {
+        let snapshot = compression::compress(format::encode(json, self.format)?, self.compression)?;
+
+        return encryption::seal_snapshot(self.cipher.as_ref(), previous, snapshot);
+    }
+
+    /// Get a value in the db as any type that implements [DeserializeOwned]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        return self.default_table().get(key);
+    }
+
+    /// Find a value in the db
+    pub fn find(&self, key: &str) -> Result<Value> {
+        return self.default_table().find(key);
+    }
+
+    /// Get the value at a path like `users/alice/age` in the db as any type that implements [DeserializeOwned].
+    /// Paths use the json pointer syntax of RFC 6901 so arrays are indexed by number,
+    /// a `/` in a key is written as `~1` and a `~` as `~0`. The leading `/` is optional
+    pub fn get_path<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        return self.default_table().get_path(path);
+    }
+
+    /// Find the value at a path like `users/alice/age` in the db
+    pub fn find_path(&self, path: &str) -> Result<Value> {
+        return self.default_table().find_path(path);
+    }
+
+    /// Check if the key exists in the database
+    /// A database that is not loaded yet does not contain any keys
+    pub fn contains_key(&self, key: &str) -> bool {
+        return self.default_table().contains_key(key);
+    }
+
+    /// Return the length of items that are in the main tree
+    /// A database that is not loaded yet has no items
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        return self.default_table().len();
+    }
+
+    /// Return an iterator over the `(key, value)` pairs of the main tree in key order
+    /// The iterator works on a snapshot so changes made while iterating do not show up in it
+    pub fn iter(&self) -> Iter {
+        return self.default_table().iter();
+    }
+
+    /// Return an iterator over the keys of the main tree in key order
+    pub fn keys(&self) -> Keys {
+        return self.default_table().keys();
+    }
+
+    /// Return an iterator over the values of the main tree in key order
+    pub fn values(&self) -> Values {
+        return self.default_table().values();
+    }
+
+    /// Return an iterator over the entries with a key that starts with `prefix`, sorted by key
+    pub fn scan_prefix(&self, prefix: &str) -> Iter {
+        return self.default_table().scan_prefix(prefix);
+    }
+
+    /// Return an iterator over the entries with a key in `range` like `"a".."m"`, sorted by key
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, range: R) -> Iter {
+        return self.default_table().range(range);
+    }
+
+    /// Return up to `limit` entries with a key that starts with `prefix`, sorted by key.
+    /// Pass [Page::next] of a page as `after` to get the page that comes after it
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// # for i in 0..25 { db.insert(&format!("user:{:02}", i), &i).unwrap(); }
+    /// let mut after = None;
+    ///
+    /// loop {
+    ///     let page = db.page("user:", after.as_deref(), 10).unwrap();
+    ///
+    ///     for (key, value) in page.entries {
+    ///         println!("{} = {}", key, value);
+    ///     }
+    ///
+    ///     match page.next {
+    ///         Some(next) => after = Some(next),
+    ///         None => break
+    ///     }
+    /// }
+    /// ```
+    pub fn page(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Page> {
+        return self.default_table().page(prefix, after, limit);
+    }
+
+    /// Run `f` on the main tree of a loaded database
+    pub(crate) fn read<T>(&self, f: impl FnOnce(&serde_json::Value) -> T) -> Result<T> {
+        return self.read_indexed(|root, _| f(root));
+    }
+
+    /// Run `f` on the main tree and the indexes of a loaded database. Any number of readers can do this at once
+    pub(crate) fn read_indexed<T>(&self, f: impl FnOnce(&serde_json::Value, &Indexes) -> T) -> Result<T> {
+        let state = self.state.read().unwrap();
+        let state = state.as_ref().ok_or(Error::NotLoaded)?;
+
+        return Ok(f(&state.json, &state.indexes));
+    }
+}
+
+/// impl Drop for Database
+/// So changes in manual flush mode are not lost when the database goes away
+impl Drop for Database {
+    fn drop(&mut self) {
+        // A panic while a lock was held may have left the tree half changed, so it is not saved then
+        if !self.state.is_poisoned() && !self.storage.is_poisoned() {
+            let _ = self.flush();
+        }
+    }
+}
+
+/// Parse a path that points inside of a tree. The empty path would replace the tree itself
+fn inner_path(path: &str) -> Result<Vec<String>> {
+    let tokens = pointer::parse(path)?;
+
+    if tokens.is_empty() {
+        return Err(Error::InvalidPath(path.to_string()));
+    }
+
+    return Ok(tokens);
+}
+
+/// The struct that allows you to create sub trees in the main tree in the database
+/// Sub trees do not auto insert in the main tree of the database
+/// You can do that by doing
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # let path = std::env::temp_dir().join(format!("dino-doc-hello-{}.dino", std::process::id()));
+/// # let path = path.to_str().unwrap();
+/// // Create the database instance
+/// let mut db = Database::new(path);
+/// 
+/// // Load and create the database if does not exist
+/// db.load().unwrap();
+///
+/// // Create a new sub Tree in the main Tree of the db
+/// let mut data_tree = Tree::new();
+///
+/// // Insert the [data_tree] under the main tree
+/// db.insert_tree("id", data_tree).unwrap();
+/// ```
+/// Where the key always need to be a [String]
+
+#[derive(Debug)]
+pub struct Tree {
+    children: Option<serde_json::Value>
+}
+
+impl Tree {
+    /// Create a new sub tree
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Tree {
+        return Tree {
+            children: Some(serde_json::Value::Object(serde_json::Map::new()))
+        }
+    }
+
+    /// Create a new Tree from String value
+    /// The string has to be a json object
+    pub fn from(value: &str) -> Result<Tree> {
+        let children: serde_json::Value = serde_json::from_str(value)?;
+
+        if !children.is_object() {
+            return Err(Error::mismatch("tree", &children));
+        }
+
+        return Ok(Tree {
+            children: Some(children)
+        })
+    }
+
+    /// Insert data in the sub tree
+    /// The value can be anything that implements [Serialize], like your own structs and enums
+    pub fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::to_value(value)?);
+
+        return Ok(());
+    }
+
+    /// Insert data with [usize] value type in the sub tree
+    pub fn insert_number(&mut self, key: &str, value: usize) {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    }
+
+    pub fn insert_array(&mut self, key: &str, value: Vec<&str>) {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    }
+
+    pub fn insert_bool(&mut self, key: &str, value: bool) {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), serde_json::json!(value));
+    }
+
+    /// Return the length of items that are in the sub tree
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&mut self) -> usize {
+        return self.children.as_mut().unwrap().as_object_mut().unwrap().len();
+    }
+
+    /// Return an iterator over the `(key, value)` pairs of the sub tree in key order
+    pub fn iter(&self) -> Iter {
+        return Iter::new(self.map().clone());
+    }
+
+    /// Return an iterator over the keys of the sub tree in key order
+    pub fn keys(&self) -> Keys {
+        return Keys::new(self.map().clone());
+    }
+
+    /// Return an iterator over the values of the sub tree in key order
+    pub fn values(&self) -> Values {
+        return Values::new(self.map().clone());
+    }
+
+    /// Return an iterator over the entries with a key that starts with `prefix`, sorted by key
+    pub fn scan_prefix(&self, prefix: &str) -> Iter {
+        return self.scan(&.., prefix);
+    }
+
+    /// Return an iterator over the entries with a key in `range` like `"a".."m"`, sorted by key
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, range: R) -> Iter {
+        return self.scan(&range, "");
+    }
+
+    /// Return up to `limit` entries with a key that starts with `prefix`, sorted by key.
+    /// Pass [Page::next] of a page as `after` to get the page that comes after it.
+    /// A `limit` of `0` returns [Error::InvalidQuery]
+    pub fn page(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Page> {
+        return iter::page(&self.sorted_keys(), prefix, after, limit, |key| self.map().get(key));
+    }
+
+    /// The entries with a key in `range` that starts with `prefix`, sorted by key
+    fn scan<'a, R: RangeBounds<&'a str> + ?Sized>(&self, range: &R, prefix: &str) -> Iter {
+        let keys = self.sorted_keys();
+
+        return Iter::sorted(iter::scan(&keys, range, prefix).filter_map(|key| self.map().get(key).map(|val| (key, val))));
+    }
+
+    /// The keys of the sub tree in order. A sub tree has no index that keeps them, so they are sorted here
+    fn sorted_keys(&self) -> std::collections::BTreeSet<String> {
+        return self.map().keys().cloned().collect();
+    }
+
+    fn map(&self) -> &serde_json::Map<String, serde_json::Value> {
+        return self.children.as_ref().unwrap().as_object().unwrap();
+    }
+
+    /// Remove a key in the sub tree in the database with its value
+    pub fn remove(&mut self, key: &str) {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().remove(key);
+    }
+
+    /// Get a value in the sub tree as any type that implements [DeserializeOwned]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        return match self.children.as_ref().unwrap().get(key) {
+            Some(val) => Ok(T::deserialize(val)?),
+            None => Err(Error::NotFound(key.to_string()))
+        }
+    }
+
+    /// Find a value in the sub tree in the database
+    pub fn find(&self, key: &str) -> Result<Value> {
+        return match self.children.as_ref().unwrap().get(key) {
+            Some(val) => Ok(Value::from(val.clone())),
+            None => Err(Error::NotFound(key.to_string()))
+        }
+    }
+
+    /// Check if the key exists in the sub tree of the main database
+    pub fn contains_key(&mut self, key: &str) -> bool {
+        return self.children.as_mut().unwrap().as_object_mut().unwrap().contains_key(key);
+    }
+
+    /// Get the value at a path like `users/alice/age` in the sub tree. See [Database::get_path] for the path syntax
+    pub fn get_path<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        return match pointer::get(self.children.as_ref().unwrap(), &pointer::parse(path)?) {
+            Some(val) => Ok(T::deserialize(val)?),
+            None => Err(Error::NotFound(path.to_string()))
+        }
+    }
+
+    /// Find the value at a path like `users/alice/age` in the sub tree
+    pub fn find_path(&self, path: &str) -> Result<Value> {
+        return match pointer::get(self.children.as_ref().unwrap(), &pointer::parse(path)?) {
+            Some(val) => Ok(Value::from(val.clone())),
+            None => Err(Error::NotFound(path.to_string()))
+        }
+    }
+
+    /// Set the value at a path like `users/alice/age` in the sub tree
+    /// Trees on the way that do not exist yet are created
+    pub fn set_path<T: Serialize + ?Sized>(&mut self, path: &str, value: &T) -> Result<()> {
+        pointer::set(self.children.as_mut().unwrap(), &inner_path(path)?, serde_json::to_value(value)?)?;
+
+        return Ok(());
+    }
+
+    /// Remove the value at a path like `users/alice/age` in the sub tree
+    pub fn remove_path(&mut self, path: &str) -> Result<()> {
+        pointer::remove(self.children.as_mut().unwrap(), &inner_path(path)?)?;
+
+        return Ok(());
+    }
+
+    /// Insert a key with a subtree in the subtree!
+    pub fn insert_tree(&mut self, key: &str, value: Tree) {
+        self.children.as_mut().unwrap().as_object_mut().unwrap().insert(key.to_string(), value.children.unwrap());
+    }
+}
+
+/// impl IntoIterator for Tree
+/// So we can loop over the `(key, value)` pairs of a tree
+impl IntoIterator for Tree {
+    type Item = (String, Value);
+    type IntoIter = Iter;
+
+    fn into_iter(self) -> Iter {
+        return match self.children {
+            Some(serde_json::Value::Object(map)) => Iter::new(map),
+            _ => Iter::new(serde_json::Map::new())
+        }
+    }
+}
+
+/// impl FromIterator for Tree
+/// So `(key, value)` pairs can be collected into a tree
+impl std::iter::FromIterator<(String, Value)> for Tree {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Tree {
+        let map = iter.into_iter().map(|(key, value)| (key, value.val)).collect();
+
+        return Tree {
+            children: Some(serde_json::Value::Object(map))
+        }
+    }
+}
+
+/// This struct is returned when you find something in the database.
+/// Value also impls fmt::Display
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    val: serde_json::Value
+}
+
+/// Impl for Value struct
+impl Value {
+    /// Value from a json value
+    fn from(val: serde_json::Value) -> Value {
+        return Value {
+            val
+        }
+    }
+
+    /// Return the string value
+    pub fn to_string(&self) -> Result<String> {
+        return self.val.as_str().map(String::from).ok_or_else(|| Error::mismatch("string", &self.val));
+    }
+
+    /// Return the number value
+    pub fn to_number(&self) -> Result<usize> {
+        return self.val.as_u64().map(|number| number as usize).ok_or_else(|| Error::mismatch("number", &self.val));
+    }
+
+    /// Return the Tree value
+    pub fn to_tree(&self) -> Result<Tree> {
+        if !self.val.is_object() {
+            return Err(Error::mismatch("tree", &self.val));
+        }
+
+        return Ok(Tree {
+            children: Some(self.val.clone())
+        })
+    }
+
+    /// Return the Array value
+    pub fn to_vec(&self) -> Result<Vec<String>> {
+        let array = self.val.as_array().ok_or_else(|| Error::mismatch("array", &self.val))?;
+
+        return array.iter()
+            .map(|item| item.as_str().map(String::from).ok_or_else(|| Error::mismatch("string", item)))
+            .collect();
+    }
+
+    /// Return the bool value
+    pub fn to_bool(&self) -> Result<bool> {
+        return self.val.as_bool().ok_or_else(|| Error::mismatch("bool", &self.val));
+    }
+
+    pub fn to_json(&self) -> &serde_json::Value {
+        return &self.val;
+    } 
+}
+
+/// impl Serialize for Tree
+/// So a sub tree can be stored with [Database::insert] like any other value
+impl Serialize for Tree {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        return self.children.as_ref().unwrap().serialize(serializer);
+    }
+}
+
+/// impl Serialize for Value
+/// So a value that was read can be written back as it is
+impl Serialize for Value {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        return self.val.serialize(serializer);
+    }
+}
+
+/// impl Display for Value
+/// So we can print the Value to the screen
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.val {
+            serde_json::Value::Null => {
+                write!(f, "")
+            },
+
+            serde_json::Value::Bool(ref v) => {
+                write!(f, "{}", v)
+            },
+
+            serde_json::Value::String(ref v) => {
+                write!(f, "{}", v)
+            },
+
+            serde_json::Value::Number(ref v) => {
+                write!(f, "{}", v)
+            },
+
+            serde_json::Value::Array(ref v) => {
+                write!(f, "{}", serde_json::to_string_pretty(v).unwrap())
+            },
+
+            serde_json::Value::Object(ref v) => {
+                write!(f, "{}", serde_json::to_string_pretty(v).unwrap())
+            },
+        }
+    }
+}
+
+/// impl Display for Tree
+/// So we can print the tree to the display
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", serde_json::to_string_pretty(&self.children).unwrap());
+    }
+}
+
+/// impl Display for database
+/// So we can print the whole tree to the display
+impl fmt::Display for Database {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.read().unwrap();
+
+        return write!(f, "{}", serde_json::to_string_pretty(&state.as_ref().map(|state| &state.json)).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    use storage::tmp_path;
+
+    /// A fresh database path in the temp dir so tests do not step on each other
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dino-{}-{}.dino", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(tmp_path(&path));
+
+        return path.to_str().unwrap().to_string();
+    }
+
+    #[test]
+    fn save_replaces_file_atomically() {
+        let path = temp_db("atomic");
+
+        // A leftover temporary file from a crashed save must not break anything
+        fs::write(tmp_path(Path::new(&path)), "{ half written").unwrap();
+
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+        db.insert("key", "value").unwrap();
+
+        assert!(!tmp_path(Path::new(&path)).exists());
+
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.find("key").unwrap().to_string().unwrap(), "value");
+    }
+
+    #[test]
+    fn journal_replays_and_compacts() {
+        let path = temp_db("journal");
+        let journal_path = journal::path(Path::new(&path));
+        let _ = fs::remove_file(&journal_path);
+
+        let mut db = Database::new(&path);
+        db.enable_journal(0);
+        db.load().unwrap();
+
+        db.insert("a", "1").unwrap();
+        db.insert("b", "2").unwrap();
+        db.remove("a").unwrap();
+
+        // Nothing but the journal has been written yet
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        assert_eq!(fs::read_to_string(&journal_path).unwrap().lines().count(), 3);
+
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.enable_journal(0);
+        reloaded.load().unwrap();
+        assert!(!reloaded.contains_key("a"));
+        assert_eq!(reloaded.find("b").unwrap().to_string().unwrap(), "2");
+
+        reloaded.compact().unwrap();
+        assert_eq!(fs::read_to_string(&journal_path).unwrap(), "");
+
+        // Opening without the journal folds a torn journal in and clears it
+        fs::write(&journal_path, "{\"op\":\"set\",\"key\":\"c\",\"value\":3}\n{\"op\":\"se").unwrap();
+
+        drop(reloaded);
+
+        let mut plain = Database::new(&path);
+        plain.load().unwrap();
+        assert_eq!(plain.find("c").unwrap().to_number().unwrap(), 3);
+        assert_eq!(plain.len(), 2);
+        assert_eq!(fs::read_to_string(&journal_path).unwrap(), "");
+    }
+
+    #[test]
+    fn errors_instead_of_panics() {
+        let path = temp_db("errors");
+
+        let db = Database::new(&path);
+        assert!(matches!(db.insert("key", "value"), Err(Error::NotLoaded)));
+        assert!(matches!(db.find("key"), Err(Error::NotLoaded)));
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(Database::new(&path).load(), Err(Error::Parse(_))));
+
+        fs::write(&path, "[]").unwrap();
+        assert!(matches!(Database::new(&path).load(), Err(Error::TypeMismatch { expected: "tree", found: "array" })));
+
+        fs::write(&path, "{}").unwrap();
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+        db.insert("key", "value").unwrap();
+
+        assert!(matches!(db.find("missing"), Err(Error::NotFound(_))));
+        assert!(matches!(db.find("key").unwrap().to_number(), Err(Error::TypeMismatch { expected: "number", found: "string" })));
+        assert!(matches!(Tree::from("1"), Err(Error::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn serde_insert_and_get() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        enum Role { Admin, Guest { until: u64 } }
+
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct User { name: String, age: i32, score: f64, nick: Option<String>, roles: Vec<Role>, grid: Vec<Vec<i8>> }
+
+        let user = User {
+            name: "alice".to_string(),
+            age: -3,
+            score: 0.5,
+            nick: None,
+            roles: vec![Role::Admin, Role::Guest { until: 10 }],
+            grid: vec![vec![1, -1], vec![]]
+        };
+
+        let path = temp_db("serde");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+        db.insert("alice", &user).unwrap();
+        db.insert("temperature", &-1.5).unwrap();
+
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get::<User>("alice").unwrap(), user);
+        assert_eq!(reloaded.get::<f64>("temperature").unwrap(), -1.5);
+        assert!(matches!(reloaded.get::<bool>("temperature"), Err(Error::Parse(_))));
+        assert!(matches!(reloaded.get::<bool>("missing"), Err(Error::NotFound(_))));
+
+        let mut tree = Tree::new();
+        tree.insert("user", &user).unwrap();
+        tree.insert("maybe", &Some(7i64)).unwrap();
+        assert_eq!(tree.get::<User>("user").unwrap(), user);
+        assert_eq!(tree.get::<Option<i64>>("maybe").unwrap(), Some(7));
+    }
+
+    #[test]
+    fn paths_into_nested_trees() {
+        let path = temp_db("paths");
+        let journal_path = journal::path(Path::new(&path));
+        let _ = fs::remove_file(&journal_path);
+
+        let mut db = Database::new(&path);
+        db.enable_journal(0);
+        db.load().unwrap();
+
+        db.set_path("users/alice/age", &30).unwrap();
+        db.set_path("/users/alice/tags", &["a"]).unwrap();
+        db.set_path("/users/alice/tags/-", "b").unwrap();
+        db.set_path("/users/a~1b~0c", &true).unwrap();
+
+        assert_eq!(db.get_path::<u32>("/users/alice/age").unwrap(), 30);
+        assert_eq!(db.get_path::<Vec<String>>("users/alice/tags").unwrap(), vec!["a", "b"]);
+        assert_eq!(db.find_path("/users/alice/tags/1").unwrap().to_string().unwrap(), "b");
+        assert!(db.find("users").unwrap().to_tree().unwrap().contains_key("a/b~c"));
+
+        assert!(matches!(db.set_path("/users/alice/tags/5", "x"), Err(Error::InvalidPath(_))));
+        assert!(matches!(db.set_path("/users/alice/age/years", &1), Err(Error::TypeMismatch { .. })));
+        assert!(matches!(db.get_path::<u32>("/users/bob/age"), Err(Error::NotFound(_))));
+        assert!(matches!(db.get_path::<u32>("/users/~2"), Err(Error::InvalidPath(_))));
+        assert!(!db.find("users").unwrap().to_tree().unwrap().contains_key("bob"));
+
+        db.remove_path("/users/alice/tags/0").unwrap();
+        db.remove_path("/users/nobody/age").unwrap();
+
+        // Replaying the journal twice (a crash between folding and clearing it) must give the same tree
+        let journal = fs::read_to_string(&journal_path).unwrap();
+        fs::write(&journal_path, format!("{}{}", journal, journal)).unwrap();
+
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get_path::<Vec<String>>("/users/alice/tags").unwrap(), vec!["b"]);
+        assert_eq!(reloaded.get_path::<u32>("/users/alice/age").unwrap(), 30);
+
+        let mut tree = Tree::new();
+        tree.set_path("a/b", &1).unwrap();
+        tree.remove_path("a/b").unwrap();
+        assert!(matches!(tree.get_path::<u32>("a/b"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn transactions_commit_or_roll_back() {
+        let path = temp_db("transaction");
+        let journal_path = journal::path(Path::new(&path));
+        let _ = fs::remove_file(&journal_path);
+
+        let mut db = Database::new(&path);
+        db.enable_journal(0);
+        db.load().unwrap();
+        db.set_path("alice/balance", &10).unwrap();
+        db.set_path("bob/balance", &0).unwrap();
+
+        db.transaction(|tx| {
+            let alice: u32 = tx.get_path("alice/balance")?;
+
+            tx.set_path("alice/balance", &(alice - 5))?;
+            tx.set_path("bob/balance", &5)?;
+
+            return Ok::<_, Error>(());
+        }).unwrap();
+
+        // The whole transaction is a single line in the journal
+        assert_eq!(fs::read_to_string(&journal_path).unwrap().lines().count(), 3);
+
+        let failed: std::result::Result<(), Error> = db.transaction(|tx| {
+            tx.remove("alice")?;
+            assert!(!tx.contains_key("alice"));
+
+            return Err(Error::NotFound("bob".to_string()));
+        });
+        assert!(failed.is_err());
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            db.transaction(|tx| -> Result<()> {
+                tx.remove("bob")?;
+
+                panic!("halfway through");
+            })
+        }));
+        assert!(panicked.is_err());
+
+        // Neither of the failed transactions left anything behind and the lock is not poisoned
+        assert!(db.contains_key("alice") && db.contains_key("bob"));
+
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get_path::<u32>("alice/balance").unwrap(), 5);
+        assert_eq!(reloaded.get_path::<u32>("bob/balance").unwrap(), 5);
+    }
+
+    #[test]
+    fn batches_and_manual_flush() {
+        let path = temp_db("batch");
+        let journal_path = journal::path(Path::new(&path));
+        let _ = fs::remove_file(&journal_path);
+
+        let mut db = Database::new(&path);
+        db.enable_journal(0);
+        db.load().unwrap();
+
+        let mut batch = WriteBatch::new();
+
+        for i in 0..100 {
+            batch.insert(&format!("key-{}", i), &i).unwrap();
+        }
+
+        batch.remove("key-0");
+        db.apply_batch(batch).unwrap();
+        assert_eq!(fs::read_to_string(&journal_path).unwrap().lines().count(), 1);
+
+        // A batch that fails halfway is not applied at all
+        let mut batch = WriteBatch::new();
+        batch.remove("key-1");
+        batch.set_path("key-2/nested", &1).unwrap();
+        assert!(db.apply_batch(batch).is_err());
+        assert_eq!(db.len(), 99);
+        drop(db);
+
+        let path = temp_db("manual-flush");
+        let mut db = Database::new(&path);
+        db.enable_manual_flush();
+        db.load().unwrap();
+
+        db.insert("a", &1).unwrap();
+        db.insert("b", &2).unwrap();
+        assert!(db.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        db.flush().unwrap();
+        assert!(!db.is_dirty());
+        assert!(fs::read_to_string(&path).unwrap().contains("\"b\""));
+
+        // Dropping the database flushes it
+        db.remove("a").unwrap();
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert!(!reloaded.contains_key("a"));
+        assert_eq!(reloaded.get::<u32>("b").unwrap(), 2);
+    }
+
+    #[test]
+    fn drop_after_poisoned_lock() {
+        let mut db = Database::in_memory();
+        db.enable_manual_flush();
+        db.insert("a", &1).unwrap();
+
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = db.state.write().unwrap();
+
+            panic!("poison the lock");
+        }));
+
+        assert!(db.state.is_poisoned());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(db))).is_ok());
+    }
+
+    #[test]
+    fn file_locking() {
+        let path = temp_db("locking");
+
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+        db.insert("key", "value").unwrap();
+
+        assert!(matches!(Database::new(&path).load(), Err(Error::Locked(_))));
+
+        let mut reader = Database::new(&path);
+        reader.enable_read_only();
+        assert!(matches!(reader.load(), Err(Error::Locked(_))));
+
+        // Waiting for the lock works once the writer lets go of it
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(db);
+        });
+
+        reader.set_lock_timeout(Duration::from_secs(5));
+        reader.load().unwrap();
+        handle.join().unwrap();
+
+        // Readers share the lock but keep writers out
+        let mut other = Database::new(&path);
+        other.enable_read_only();
+        other.load().unwrap();
+        assert!(matches!(Database::new(&path).load(), Err(Error::Locked(_))));
+
+        assert_eq!(other.find("key").unwrap().to_string().unwrap(), "value");
+        assert!(matches!(other.insert("key", "other"), Err(Error::ReadOnly)));
+        assert!(matches!(other.compact(), Err(Error::ReadOnly)));
+
+        // Loading again does not trip over our own lock
+        other.load().unwrap();
+
+        // A read only database does not create a lock file, so it works on read only media
+        let path = temp_db("locking-read-only");
+        fs::write(&path, "{ \"key\": \"value\" }").unwrap();
+
+        let mut reader = Database::new(&path);
+        reader.enable_read_only();
+        reader.load().unwrap();
+
+        assert_eq!(reader.get::<String>("key").unwrap(), "value");
+        assert!(!lock::path(Path::new(&path)).exists());
+    }
+
+    #[test]
+    fn iterate_keys_and_values() {
+        let path = temp_db("iter");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.insert("b", &2).unwrap();
+        db.insert("a", &1).unwrap();
+        db.insert("c", &3).unwrap();
+
+        let iter = db.iter();
+
+        // Changes after the iterator was made do not show up in it
+        db.remove("a").unwrap();
+
+        let entries: Vec<(String, usize)> = iter.map(|(key, value)| (key, value.to_number().unwrap())).collect();
+        assert_eq!(entries, vec![("a".to_string(), 1), ("b".to_string(), 2), ("c".to_string(), 3)]);
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(db.values().rev().map(|value| value.to_number().unwrap()).collect::<Vec<_>>(), vec![3, 2]);
+
+        let tree: Tree = db.iter().filter(|(key, _)| key != "b").collect();
+        assert_eq!(tree.keys().collect::<Vec<_>>(), vec!["c"]);
+        assert_eq!(tree.into_iter().len(), 1);
+    }
+
+    #[test]
+    fn prefix_range_and_page_scans() {
+        let path = temp_db("scans");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        for key in ["user:3", "session:1", "user:1", "user:2", "userx", "session:2"].iter() {
+            db.insert(key, key).unwrap();
+        }
+
+        assert_eq!(db.scan_prefix("user:").map(|(key, _)| key).collect::<Vec<_>>(), vec!["user:1", "user:2", "user:3"]);
+        assert_eq!(db.range("session:2".."user:2").map(|(key, _)| key).collect::<Vec<_>>(), vec!["session:2", "user:1"]);
+        assert_eq!(db.range(.."session:2").count(), 1);
+
+        let first = db.page("user:", None, 2).unwrap();
+        assert_eq!(first.entries.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["user:1", "user:2"]);
+        assert_eq!(first.next.as_deref(), Some("user:2"));
+
+        let second = db.page("user:", first.next.as_deref(), 2).unwrap();
+        assert_eq!(second.entries.len(), 1);
+        assert_eq!(second.next, None);
+
+        // A page of nothing would have no cursor to go on from
+        assert!(matches!(db.page("user:", None, 0), Err(Error::InvalidQuery(_))));
+
+        // A range that ends before it starts is just empty
+        assert_eq!(db.range("user:2".."session:2").count(), 0);
+        assert_eq!(db.range("user:2".."user:2").count(), 0);
+
+        // The keys in order follow every change
+        db.remove("user:1").unwrap();
+        db.table("users").insert("user:9", &9).unwrap();
+        assert_eq!(db.scan_prefix("user:").map(|(key, _)| key).collect::<Vec<_>>(), vec!["user:2", "user:3"]);
+        assert_eq!(db.table("users").page("", None, 10).unwrap().entries.len(), 1);
+
+        db.rename_table("users", "people").unwrap();
+        assert_eq!(db.table("people").scan_prefix("user:").count(), 1);
+        assert_eq!(db.table("users").scan_prefix("user:").count(), 0);
+
+        let tree: Tree = db.iter().collect();
+        assert_eq!(tree.scan_prefix("session:").count(), 2);
+        assert_eq!(tree.page("", Some("user:3"), 10).unwrap().entries[0].0, "userx");
+    }
+
+    #[test]
+    fn named_tables() {
+        let path = temp_db("tables");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.insert("flat", &1).unwrap();
+        db.table("orders").insert("order-1", &10).unwrap();
+        db.table("orders").set_path("order-2/total", &20).unwrap();
+        db.table("users").insert("alice", "Alice").unwrap();
+
+        // The default table does not see the named tables
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec!["flat"]);
+        assert_eq!(db.len(), 1);
+        assert!(!db.contains_key("order-1"));
+        assert!(matches!(db.insert("$tables", &1), Err(Error::InvalidPath(_))));
+        assert!(matches!(db.get_path::<u32>("$tables/orders/order-1"), Err(Error::NotFound(_))));
+
+        let orders = db.table("orders");
+        assert_eq!(orders.name(), Some("orders"));
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders.get::<u32>("order-1").unwrap(), 10);
+        assert_eq!(orders.get_path::<u32>("order-2/total").unwrap(), 20);
+        assert_eq!(orders.scan_prefix("order-").count(), 2);
+        assert_eq!(db.tables(), vec!["orders", "users"]);
+
+        db.rename_table("orders", "sales").unwrap();
+        assert!(matches!(db.rename_table("orders", "sales"), Err(Error::NotFound(_))));
+        assert!(matches!(db.rename_table("users", "sales"), Err(Error::AlreadyExists(_))));
+        assert!(db.table("orders").is_empty());
+        assert_eq!(db.table("sales").get::<u32>("order-1").unwrap(), 10);
+
+        db.drop_table("users").unwrap();
+        db.drop_table("missing").unwrap();
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.tables(), vec!["sales"]);
+        assert_eq!(reloaded.table("sales").len(), 2);
+        assert_eq!(reloaded.get::<u32>("flat").unwrap(), 1);
+    }
+
+    #[test]
+    fn document_ids() {
+        let path = temp_db("docs");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        let first = db.insert_doc(&serde_json::json!({ "name": "Alice" })).unwrap();
+        let second = db.insert_doc(&serde_json::json!({ "name": "Bob" })).unwrap();
+        assert_eq!((first, second), (DocId(1), DocId(2)));
+
+        db.update_doc(first, &serde_json::json!({ "name": "Alicia" })).unwrap();
+        assert_eq!(db.get_path::<String>("1/name").unwrap(), "Alicia");
+        assert!(matches!(db.update_doc(DocId(7), &1), Err(Error::NotFound(_))));
+
+        // Removing the newest document does not free its id and taken keys are skipped
+        db.remove_doc(second).unwrap();
+        db.insert("3", &"by hand").unwrap();
+        assert_eq!(db.insert_doc(&0).unwrap(), DocId(4));
+
+        // Every table counts on its own
+        assert_eq!(db.table("users").insert_doc(&0).unwrap(), DocId(1));
+        db.rename_table("users", "people").unwrap();
+        assert_eq!(db.table("people").insert_doc(&0).unwrap(), DocId(2));
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.insert_doc(&0).unwrap(), DocId(5));
+        assert_eq!(reloaded.keys().collect::<Vec<_>>(), vec!["1", "3", "4", "5"]);
+        assert_eq!("4".parse::<DocId>().unwrap(), DocId(4));
+        assert!(matches!("four".parse::<DocId>(), Err(Error::InvalidId(_))));
+
+        reloaded.drop_table("people").unwrap();
+        assert_eq!(reloaded.table("people").insert_doc(&0).unwrap(), DocId(1));
+    }
+
+    #[test]
+    fn queries() {
+        let path = temp_db("queries");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.insert("alice", &serde_json::json!({ "age": 35, "active": true, "tags": ["admin"], "address": { "city": "Oslo" } })).unwrap();
+        db.insert("bob", &serde_json::json!({ "age": 25, "active": true, "tags": [] })).unwrap();
+        db.insert("carol", &serde_json::json!({ "age": 41.0, "active": false, "tags": ["admin", "ops"] })).unwrap();
+        db.insert("dave", &serde_json::json!({ "age": 52, "active": true, "name": "Dave" })).unwrap();
+        db.insert("flat", &1).unwrap();
+
+        let keys = |results: Vec<(String, Value)>| results.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+        assert_eq!(keys(db.query().where_("age").gt(30).and("active").eq(true).run().unwrap()), vec!["alice", "dave"]);
+        assert_eq!(keys(db.query().where_("age").eq(41).run().unwrap()), vec!["carol"]);
+        assert_eq!(keys(db.query().where_("age").lt(30).run().unwrap()), vec!["bob"]);
+        assert_eq!(keys(db.query().where_("active").ne(true).run().unwrap()), vec!["carol", "flat"]);
+        assert_eq!(keys(db.query().where_("age").in_(&[25, 52]).run().unwrap()), vec!["bob", "dave"]);
+        assert_eq!(keys(db.query().where_("name").exists(true).run().unwrap()), vec!["dave"]);
+        assert_eq!(keys(db.query().where_("address/city").regex("^Os").run().unwrap()), vec!["alice"]);
+        assert_eq!(keys(db.query().where_("tags").contains("admin").run().unwrap()), vec!["alice", "carol"]);
+        assert_eq!(db.query().where_("age").exists(true).count().unwrap(), 4);
+
+        let sorted = db.query().where_("age").exists(true).sort("age", Order::Desc).skip(1).limit(2).run().unwrap();
+        assert_eq!(keys(sorted), vec!["carol", "alice"]);
+
+        let projected = db.query().where_("age").gt(30).select(&["age", "address/city"]).limit(1).run().unwrap();
+        assert_eq!(projected[0].1.to_json(), &serde_json::json!({ "age": 35, "address": { "city": "Oslo" } }));
+
+        assert!(matches!(db.query().where_("name").regex("(").run(), Err(Error::InvalidQuery(_))));
+        assert!(matches!(db.query().where_("~2").eq(1).run(), Err(Error::InvalidPath(_))));
+
+        db.table("orders").insert("order-1", &serde_json::json!({ "total": 10 })).unwrap();
+        assert_eq!(db.table("orders").query().where_("total").gt(5).count().unwrap(), 1);
+        assert_eq!(db.query().where_("total").exists(true).count().unwrap(), 0);
+
+        // Integers above 2^53 are compared exactly instead of being rounded to the same float
+        db.table("big").insert("max", &serde_json::json!({ "id": u64::MAX })).unwrap();
+        db.table("big").insert("below", &serde_json::json!({ "id": u64::MAX - 1 })).unwrap();
+        assert_eq!(keys(db.table("big").query().where_("id").eq(u64::MAX - 1).run().unwrap()), vec!["below"]);
+        assert_eq!(keys(db.table("big").query().where_("id").gt(u64::MAX - 1).run().unwrap()), vec!["max"]);
+        assert_eq!(keys(db.table("big").query().where_("id").in_(&[u64::MAX]).run().unwrap()), vec!["max"]);
+        assert_eq!(keys(db.table("big").find_by("id", &(u64::MAX - 1)).unwrap()), vec!["below"]);
+        assert_eq!(keys(db.table("big").query().where_("id").gt(1.5).run().unwrap()), vec!["below", "max"]);
+    }
+
+    #[test]
+    fn update_operators() {
+        use serde_json::json;
+
+        let path = temp_db("update");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.insert("alice", &json!({ "name": "alice", "age": 30, "tags": ["a"], "score": 5 })).unwrap();
+        db.insert("bob", &json!({ "name": "bob", "age": 40, "tags": ["a", "b", "a"], "score": 5.5 })).unwrap();
+
+        let updated = db.update(&json!({ "age": { "$gt": 25 } }), &json!({
+            "$set": { "address/city": "Oslo" },
+            "$inc": { "age": 1, "visits": 2 },
+            "$push": { "tags": "c" },
+            "$max": { "score": 6 }
+        })).unwrap();
+        assert_eq!(updated, Updated { matched: 2, modified: 2, upserted: None });
+
+        assert_eq!(db.get::<serde_json::Value>("alice").unwrap(), json!({
+            "name": "alice", "age": 31, "tags": ["a", "c"], "score": 6, "visits": 2, "address": { "city": "Oslo" }
+        }));
+
+        db.update(&json!({ "name": "bob" }), &json!({
+            "$pull": { "tags": "a" },
+            "$addToSet": { "tags": "b" },
+            "$unset": { "address": "" },
+            "$rename": { "visits": "stats/visits" },
+            "$min": { "age": 18 }
+        })).unwrap();
+        assert_eq!(db.get::<serde_json::Value>("bob").unwrap(), json!({
+            "name": "bob", "age": 18, "tags": ["b", "c"], "score": 6, "stats": { "visits": 2 }
+        }));
+
+        // Nothing changes when one of the updates fails
+        assert!(matches!(db.update(&json!({}), &json!({ "$inc": { "name": 1 } })), Err(Error::TypeMismatch { .. })));
+        assert!(matches!(db.update(&json!({}), &json!({ "$explode": { "name": 1 } })), Err(Error::InvalidQuery(_))));
+        assert_eq!(db.get_path::<String>("alice/name").unwrap(), "alice");
+
+        // Big unsigned integers keep their precision and overflowing is an error instead of rounding
+        db.insert("counter", &json!({ "hits": u64::MAX - 2 })).unwrap();
+        db.update(&json!({ "hits": { "$gt": 0 } }), &json!({ "$inc": { "hits": 1 } })).unwrap();
+        assert_eq!(db.get_path::<u64>("counter/hits").unwrap(), u64::MAX - 1);
+        assert!(matches!(db.update(&json!({ "hits": { "$gt": 0 } }), &json!({ "$inc": { "hits": 2 } })), Err(Error::Overflow(_))));
+        assert_eq!(db.get_path::<u64>("counter/hits").unwrap(), u64::MAX - 1);
+        db.set_path("counter/score", &f64::MAX).unwrap();
+        assert!(matches!(db.update(&json!({ "hits": { "$gt": 0 } }), &json!({ "$inc": { "score": f64::MAX } })), Err(Error::Overflow(_))));
+        assert_eq!(db.get_path::<f64>("counter/score").unwrap(), f64::MAX);
+        db.update(&json!({ "hits": { "$gt": 0 } }), &json!({ "$max": { "hits": u64::MAX } })).unwrap();
+        assert_eq!(db.get_path::<u64>("counter/hits").unwrap(), u64::MAX);
+        db.remove("counter").unwrap();
+
+        assert_eq!(db.update(&json!({ "name": "carol" }), &json!({ "$set": { "age": 1 } })).unwrap().matched, 0);
+
+        let upserted = db.table("users").upsert(&json!({ "name": "carol" }), &json!({ "$set": { "age": 22 } })).unwrap();
+        assert_eq!(upserted.upserted, Some(DocId(1)));
+        assert_eq!(db.table("users").get_doc::<serde_json::Value>(DocId(1)).unwrap(), json!({ "name": "carol", "age": 22 }));
+
+        let again = db.table("users").upsert(&json!({ "name": "carol" }), &json!({ "$set": { "age": 22 } })).unwrap();
+        assert_eq!(again, Updated { matched: 1, modified: 0, upserted: None });
+    }
+
+    #[test]
+    fn secondary_indexes() {
+        use serde_json::json;
+
+        let path = temp_db("indexes");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.insert("alice", &json!({ "email": "a@b.c", "city": "Oslo" })).unwrap();
+        db.insert("bob", &json!({ "email": "b@b.c", "city": "Oslo" })).unwrap();
+
+        let keys = |found: Vec<(String, Value)>| found.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+        // Without an index every entry is looked at
+        assert_eq!(keys(db.find_by("city", "Oslo").unwrap()), vec!["alice", "bob"]);
+
+        db.create_unique_index("email").unwrap();
+        db.create_index("city").unwrap();
+        assert_eq!(db.indexes(), vec!["city", "email"]);
+        assert!(matches!(db.create_unique_index("city"), Err(Error::Duplicate { .. })));
+        assert_eq!(db.indexes(), vec!["city", "email"]);
+
+        assert_eq!(keys(db.find_by("email", "b@b.c").unwrap()), vec!["bob"]);
+        assert_eq!(keys(db.find_by("city", "Oslo").unwrap()), vec!["alice", "bob"]);
+
+        // Changes keep the indexes up to date and cannot break a unique index
+        db.set_path("bob/city", "Bergen").unwrap();
+        db.remove("alice").unwrap();
+        assert_eq!(keys(db.find_by("city", "Bergen").unwrap()), vec!["bob"]);
+        assert!(db.find_by("city", "Oslo").unwrap().is_empty());
+
+        assert!(matches!(db.insert("carol", &json!({ "email": "b@b.c" })), Err(Error::Duplicate { .. })));
+        assert!(!db.contains_key("carol"));
+
+        let mut batch = WriteBatch::new();
+        batch.insert("carol", &json!({ "email": "c@b.c" })).unwrap();
+        batch.insert("dave", &json!({ "email": "c@b.c" })).unwrap();
+        assert!(matches!(db.apply_batch(batch), Err(Error::Duplicate { .. })));
+
+        // Swapping two unique values at once is fine
+        db.transaction(|tx| {
+            tx.set_path("bob/email", "x@b.c")?;
+            tx.insert("carol", &json!({ "email": "b@b.c" }))?;
+
+            return Ok::<_, Error>(());
+        }).unwrap();
+        assert_eq!(keys(db.find_by("email", "b@b.c").unwrap()), vec!["carol"]);
+
+        db.table("users").create_index("age").unwrap();
+        db.table("users").insert_doc(&json!({ "age": 30 })).unwrap();
+        db.rename_table("users", "people").unwrap();
+        assert_eq!(db.table("people").indexes(), vec!["age"]);
+        assert_eq!(db.table("people").find_by("age", &30.0).unwrap().len(), 1);
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.indexes(), vec!["city", "email"]);
+        assert_eq!(keys(reloaded.find_by("email", "x@b.c").unwrap()), vec!["bob"]);
+        assert_eq!(reloaded.table("people").find_by("age", &30).unwrap().len(), 1);
+
+        reloaded.drop_index("email").unwrap();
+        reloaded.insert("eve", &json!({ "email": "b@b.c" })).unwrap();
+        reloaded.drop_table("people").unwrap();
+        assert!(reloaded.table("people").indexes().is_empty());
+    }
+
+    #[test]
+    fn schema_validation() {
+        use serde_json::json;
+
+        let path = temp_db("schema");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        db.set_schema(Schema::json(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        })).unwrap()).unwrap();
+
+        db.insert("alice", &json!({ "name": "Alice", "age": 30, "tags": ["admin"] })).unwrap();
+
+        let invalid = |result: Result<()>| match result {
+            Err(Error::Invalid { path, .. }) => path,
+            other => panic!("expected an invalid write but got {:?}", other)
+        };
+
+        assert_eq!(invalid(db.insert("bob", &json!({ "nmae": "Bob" }))), "/bob/name");
+        assert_eq!(invalid(db.insert("bob", &json!({ "name": "Bob", "nmae": "Bob" }))), "/bob/nmae");
+        assert_eq!(invalid(db.set_path("alice/age", &-1)), "/alice/age");
+        assert_eq!(invalid(db.set_path("alice/tags/-", &1)), "/alice/tags/1");
+        assert_eq!(invalid(db.insert("flat", &1)), "/flat");
+        assert!(!db.contains_key("bob"));
+        assert_eq!(db.get_path::<u32>("alice/age").unwrap(), 30);
+
+        // A schema has to be valid and fit the entries that are there already
+        assert!(matches!(Schema::json(json!({ "type": "text" })), Err(Error::InvalidSchema(_))));
+        assert!(matches!(Schema::fields(&[("name", "text")]), Err(Error::InvalidSchema(_))));
+        assert!(matches!(Schema::json(json!({ "type": "string", "pattern": "(" })), Err(Error::InvalidSchema(_))));
+        assert!(matches!(db.set_schema(Schema::fields(&[("email", "string")]).unwrap()), Err(Error::Invalid { .. })));
+
+        // Every table has its own schema
+        let orders = db.table("orders");
+        orders.set_schema(Schema::fields(&[("total", "number"), ("note", "string?")]).unwrap()).unwrap();
+        orders.insert("order-1", &json!({ "total": 9.5 })).unwrap();
+        assert_eq!(invalid(orders.insert("order-2", &json!({ "total": "9.5" }))), "/order-2/total");
+        assert!(orders.insert_doc(&json!({ "total": 1 })).is_ok());
+
+        orders.set_schema(Schema::json(json!({ "properties": { "sku": { "pattern": "^[A-Z]+-[0-9]+$" } } })).unwrap()).unwrap();
+        orders.insert("order-3", &json!({ "sku": "AB-12" })).unwrap();
+        assert_eq!(invalid(orders.insert("order-4", &json!({ "sku": "ab-12" }))), "/order-4/sku");
+        drop(orders);
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert!(reloaded.schema().is_some());
+        assert!(reloaded.insert("bob", &json!({ "age": 1 })).is_err());
+
+        reloaded.remove_schema().unwrap();
+        reloaded.insert("bob", &json!({ "age": 1 })).unwrap();
+        assert_eq!(reloaded.schema(), None);
+    }
+
+    #[test]
+    fn expiring_keys() {
+        use std::time::UNIX_EPOCH;
+
+        let path = temp_db("ttl");
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+
+        let mut db = Database::new(&path);
+        db.set_clock(clock.clone());
+        db.load().unwrap();
+
+        db.insert_with_ttl("session", "abc", Duration::from_secs(10)).unwrap();
+        db.insert_with_ttl("token", "xyz", Duration::from_secs(20)).unwrap();
+        db.table("otp").insert_with_ttl("code", &1234, Duration::from_secs(10)).unwrap();
+        db.insert("user", "alice").unwrap();
+        db.expire("user", UNIX_EPOCH + Duration::from_secs(1100)).unwrap();
+        assert!(matches!(db.expire("missing", UNIX_EPOCH), Err(Error::NotFound(_))));
+
+        assert_eq!(db.ttl("session"), Some(Duration::from_secs(10)));
+        assert_eq!(db.len(), 3);
+
+        clock.advance(Duration::from_secs(10));
+
+        // Expired keys are hidden from every read
+        assert!(!db.contains_key("session"));
+        assert!(matches!(db.find("session"), Err(Error::NotFound(_))));
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec!["token", "user"]);
+        assert_eq!(db.len(), 2);
+        assert!(db.table("otp").is_empty());
+        assert!(!db.transaction(|tx| Ok::<_, Error>(tx.contains_key("session"))).unwrap());
+
+        // Reading an expired key does not remove it, that is left to purging
+        assert!(db.read(|root| root.get("session").is_some()).unwrap());
+
+        // An expired key that is not purged yet does not hold on to its unique value
+        let users = db.table("users");
+        users.create_unique_index("email").unwrap();
+        users.insert_with_ttl("old", &serde_json::json!({ "email": "a@b.c" }), Duration::from_secs(1)).unwrap();
+        assert!(matches!(users.insert("new", &serde_json::json!({ "email": "a@b.c" })), Err(Error::Duplicate { .. })));
+
+        clock.advance(Duration::from_secs(1));
+        users.insert("new", &serde_json::json!({ "email": "a@b.c" })).unwrap();
+        assert_eq!(users.find_by("email", "a@b.c").unwrap().len(), 1);
+        drop(users);
+
+        // Inserting a key again without a ttl makes it stay
+        db.insert("token", "new").unwrap();
+        assert_eq!(db.ttl("token"), None);
+        drop(db);
+
+        clock.advance(Duration::from_secs(49));
+
+        let mut reloaded = Database::new(&path);
+        reloaded.set_clock(clock.clone());
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.ttl("user"), Some(Duration::from_secs(40)));
+        assert_eq!(reloaded.get::<String>("token").unwrap(), "new");
+
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(reloaded.purge_expired().unwrap(), 4);
+        assert_eq!(reloaded.purge_expired().unwrap(), 0);
+        assert_eq!(reloaded.keys().collect::<Vec<_>>(), vec!["token"]);
+        assert_eq!(reloaded.find_path("").unwrap().to_json(), &serde_json::json!({ "token": "new" }));
+
+        // The sweeper removes expired keys in the background
+        reloaded.insert_with_ttl("short", &1, Duration::from_secs(1)).unwrap();
+        clock.advance(Duration::from_secs(2));
+
+        let db = Arc::new(reloaded);
+        let sweeper = db.start_sweeper(Duration::from_millis(5));
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while db.read(|root| root.get("short").is_some()).unwrap() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        drop(sweeper);
+        assert!(db.read(|root| root.get("short").is_none()).unwrap());
+
+        // Dropping a sweeper stops it right away, even when it has not started waiting yet
+        let started = std::time::Instant::now();
+        drop(db.start_sweeper(Duration::from_secs(60)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn change_notifications() {
+        let path = temp_db("watch");
+        let mut db = Database::new(&path);
+        db.load().unwrap();
+
+        let users = db.subscribe("user:");
+        let orders = db.table("orders").subscribe("");
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback_seen = seen.clone();
+        db.on_change(move |event| callback_seen.lock().unwrap().push((event.key.clone(), event.kind)));
+
+        db.insert("user:alice", "Alice").unwrap();
+        db.insert("user:alice", "Alicia").unwrap();
+        db.insert("user:alice", "Alicia").unwrap();
+        db.insert("other", &1).unwrap();
+        db.remove("user:alice").unwrap();
+
+        let events: Vec<Event> = users.try_iter().collect();
+        assert_eq!(events.iter().map(|event| event.kind).collect::<Vec<_>>(), vec![EventKind::Insert, EventKind::Update, EventKind::Remove]);
+        assert_eq!(events[1].old.as_ref().unwrap().to_json(), &serde_json::json!("Alice"));
+        assert_eq!(events[1].new.as_ref().unwrap().to_json(), &serde_json::json!("Alicia"));
+        assert!(events[2].new.is_none());
+
+        // Failed changes send nothing
+        db.set_schema(Schema::fields(&[("name", "string")]).unwrap()).unwrap_err();
+        db.table("orders").create_unique_index("id").unwrap();
+        db.table("orders").insert("a", &serde_json::json!({ "id": 1 })).unwrap();
+        assert!(db.table("orders").insert("b", &serde_json::json!({ "id": 1 })).is_err());
+        assert_eq!(orders.try_iter().map(|event| event.key).collect::<Vec<_>>(), vec!["a"]);
+
+        // A transaction delivers all of its events at once and only when it commits
+        db.transaction(|tx| {
+            tx.insert("user:bob", "Bob")?;
+            tx.insert("user:carol", "Carol")?;
+
+            return Err::<(), _>(Error::NotFound("abort".to_string()));
+        }).unwrap_err();
+        assert!(users.try_recv().is_err());
+
+        db.transaction(|tx| {
+            tx.insert("user:bob", "Bob")?;
+            tx.insert("user:carol", "Carol")?;
+
+            return Ok::<_, Error>(());
+        }).unwrap();
+        assert_eq!(users.try_iter().map(|event| event.key).collect::<Vec<_>>(), vec!["user:bob", "user:carol"]);
+
+        let mut batch = WriteBatch::new();
+        batch.remove("user:bob");
+        batch.insert("user:dave", "Dave").unwrap();
+        db.apply_batch(batch).unwrap();
+        assert_eq!(users.try_iter().map(|event| (event.key, event.kind)).collect::<Vec<_>>(), vec![
+            ("user:bob".to_string(), EventKind::Remove),
+            ("user:dave".to_string(), EventKind::Insert)
+        ]);
+
+        // Dropping a table removes every entry of it
+        db.drop_table("orders").unwrap();
+        assert_eq!(orders.try_iter().map(|event| (event.key, event.kind)).collect::<Vec<_>>(), vec![("a".to_string(), EventKind::Remove)]);
+
+        assert_eq!(seen.lock().unwrap().len(), 10);
+
+        // A callback can read and change the database
+        let db = Arc::new(db);
+        let weak = Arc::downgrade(&db);
+        db.on_change(move |event| {
+            if let Some(db) = weak.upgrade() {
+                if event.key == "user:erin" && event.kind == EventKind::Insert {
+                    assert!(db.contains_key("user:erin"));
+                    db.insert("user:erin", "Erin!").unwrap();
+                }
+            }
+        });
+
+        db.insert("user:erin", "Erin").unwrap();
+        assert_eq!(users.try_iter().map(|event| event.kind).collect::<Vec<_>>(), vec![EventKind::Insert, EventKind::Update]);
+        assert_eq!(db.get::<String>("user:erin").unwrap(), "Erin!");
+
+        // In manual flush mode the events wait for the flush
+        drop(db);
+
+        let mut manual = Database::new(&path);
+        manual.enable_manual_flush();
+        manual.load().unwrap();
+
+        let events = manual.subscribe("");
+        manual.insert("later", &1).unwrap();
+        assert!(events.try_recv().is_err());
+
+        manual.flush().unwrap();
+        assert_eq!(events.try_recv().unwrap().key, "later");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_database() {
+        let db = AsyncDatabase::open(Database::new(&temp_db("async"))).await.unwrap();
+
+        db.insert("alice", &serde_json::json!({ "age": 30 })).await.unwrap();
+        db.set_path("alice/city", "Oslo").await.unwrap();
+        assert_eq!(db.get_path::<String>("alice/city").await.unwrap(), "Oslo");
+        assert!(matches!(db.find("bob").await, Err(Error::NotFound(_))));
+
+        // Many writers at once all get their turn
+        let writers: Vec<_> = (0..50).map(|i| {
+            let table = db.table("counters");
+
+            tokio::spawn(async move { table.insert(&format!("key-{}", i), &i).await })
+        }).collect();
+
+        for writer in writers {
+            writer.await.unwrap().unwrap();
+        }
+
+        assert_eq!(db.table("counters").len().await, 50);
+        assert_eq!(db.tables().await, vec!["counters"]);
+
+        let moved = db.transaction(|tx| {
+            let age: u32 = tx.get_path("alice/age")?;
+            tx.set_path("alice/age", &(age + 1))?;
+
+            return Ok::<_, Error>(age + 1);
+        }).await.unwrap();
+
+        assert_eq!(moved, 31);
+        assert_eq!(db.database().get_path::<u32>("alice/age").unwrap(), 31);
+
+        let counters = db.table("counters");
+        let events = counters.subscribe("key-1");
+
+        counters.create_unique_index("n").await.unwrap();
+        counters.insert("key-1", &serde_json::json!({ "n": 1 })).await.unwrap();
+        assert!(matches!(counters.insert("key-2", &serde_json::json!({ "n": 1 })).await, Err(Error::Duplicate { .. })));
+        assert_eq!(counters.indexes().await, vec!["n"]);
+        assert_eq!(events.try_recv().unwrap().key, "key-1");
+
+        let found = counters.query(|query| query.where_("n").eq(1).run()).await.unwrap();
+        assert_eq!(found.len(), 1);
+
+        assert_eq!(counters.range("key-10".."key-12").await.count(), 2);
+        assert_eq!(counters.page("key-", None, 20).await.unwrap().next.as_deref(), Some("key-26"));
+        assert_eq!(counters.values().await.count(), 50);
+
+        counters.expire("key-1", SystemTime::now() + Duration::from_secs(60)).await.unwrap();
+        assert!(counters.ttl("key-1").await.is_some());
+    }
+
+    #[test]
+    fn pluggable_storage() {
+        let storage = MemoryStorage::new();
+
+        let mut db = Database::with_storage(storage.clone());
+        db.load().unwrap();
+
+        db.insert("a", &1).unwrap();
+        db.insert("b", &2).unwrap();
+        drop(db);
+
+        let mut reopened = Database::with_storage(storage.clone());
+        reopened.load().unwrap();
+        assert_eq!(reopened.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn pluggable_storage_journal() {
+        let storage = MemoryStorage::new();
+
+        let mut db = Database::with_storage(storage.clone());
+        db.enable_journal(3);
+        db.load().unwrap();
+
+        db.insert("a", &1).unwrap();
+        db.insert("b", &2).unwrap();
+        assert_eq!(storage.clone().read_records().unwrap().len(), 2);
+
+        // The third record folds the journal into the snapshot
+        db.insert("c", &3).unwrap();
+        assert!(storage.clone().read_records().unwrap().is_empty());
+        assert!(storage.clone().read_snapshot().unwrap().is_some());
+    }
+
+    #[test]
+    fn failing_storage() {
+        /// A storage that cannot save anything
+        struct Broken;
+
+        impl Storage for Broken {
+            fn lock(&mut self, _shared: bool, _timeout: Option<Duration>) -> Result<()> { return Ok(()); }
+            fn read_snapshot(&mut self) -> Result<Option<Vec<u8>>> { return Ok(Some(b"{ \"a\": 1 }".to_vec())); }
+            fn write_snapshot(&mut self, _snapshot: &[u8]) -> Result<()> { return Err(Error::ReadOnly); }
+            fn read_records(&mut self) -> Result<Vec<Vec<u8>>> { return Ok(Vec::new()); }
+            fn append_record(&mut self, _record: &[u8]) -> Result<()> { return Err(Error::ReadOnly); }
+            fn clear_records(&mut self) -> Result<()> { return Ok(()); }
+        }
+
+        let mut broken = Database::with_storage(Broken);
+        broken.load().unwrap();
+
+        assert!(broken.insert("b", &2).is_err());
+        assert_eq!(broken.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn in_memory_database() {
+        let db = Database::in_memory();
+        db.insert("a", &1).unwrap();
+        db.table("users").insert_doc(&serde_json::json!({ "name": "alice" })).unwrap();
+        db.create_index("name").unwrap();
+        db.transaction(|tx| tx.insert("b", &2)).unwrap();
+
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(db.table("users").len(), 1);
+        assert_eq!(db.indexes(), vec!["name"]);
+    }
+
+    #[test]
+    fn in_memory_persist_to() {
+        let path = temp_db("in_memory");
+
+        let mut db = Database::in_memory();
+        db.insert("a", &1).unwrap();
+        db.create_index("name").unwrap();
+        assert!(!Path::new(&path).exists());
+
+        db.persist_to(&path).unwrap();
+        db.insert("b", &2).unwrap();
+        drop(db);
+
+        let mut reloaded = Database::new(&path);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(reloaded.indexes(), vec!["name"]);
+    }
+
+    #[test]
+    #[allow(unused_mut)]
+    fn snapshot_formats() {
+        let mut formats = vec![Format::Json];
+
+        #[cfg(feature = "msgpack")]
+        formats.push(Format::MessagePack);
+
+        #[cfg(feature = "cbor")]
+        formats.push(Format::Cbor);
+
+        #[cfg(feature = "bincode")]
+        formats.push(Format::Bincode);
+
+        let storage = MemoryStorage::new();
+        let tree = serde_json::json!({ "name": "alice", "age": 20, "big": u64::MAX, "score": -1.5, "tags": ["a", null, true] });
+
+        for format in formats {
+            let mut db = Database::with_storage(storage.clone());
+            db.set_format(format);
+            db.load().unwrap();
+            db.insert("user", &tree).unwrap();
+            db.table("users").insert("bob", &1).unwrap();
+            drop(db);
+
+            let snapshot = storage.clone().read_snapshot().unwrap().unwrap();
+            assert_eq!(snapshot.starts_with(b"DINO"), format != Format::Json);
+
+            // The format is read from the header so a database with the default format loads it
+            let mut reloaded = Database::with_storage(storage.clone());
+            reloaded.load().unwrap();
+            assert_eq!(reloaded.get::<serde_json::Value>("user").unwrap(), tree);
+            assert_eq!(reloaded.table("users").get::<i32>("bob").unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn unknown_snapshot_format() {
+        let mut unknown = MemoryStorage::new();
+        unknown.write_snapshot(b"DINO\x09").unwrap();
+
+        let mut db = Database::with_storage(unknown);
+        assert!(matches!(db.load(), Err(Error::Encoding { .. })));
+    }
+
+    #[test]
+    #[allow(unused_mut)]
+    fn snapshot_compression() {
+        let mut compressions = vec![(Compression::None, &b"{"[..])];
+
+        #[cfg(feature = "zstd")]
+        compressions.push((Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd][..]));
+
+        #[cfg(feature = "gzip")]
+        compressions.push((Compression::Gzip, &[0x1f, 0x8b][..]));
+
+        let storage = MemoryStorage::new();
+        let tree = serde_json::json!({ "name": "alice", "friends": vec!["bob"; 100] });
+
+        for (compression, magic) in compressions {
+            let mut db = Database::with_storage(storage.clone());
+            db.set_compression(compression);
+            db.load().unwrap();
+            db.insert("user", &tree).unwrap();
+            drop(db);
+
+            let snapshot = storage.clone().read_snapshot().unwrap().unwrap();
+            assert!(snapshot.starts_with(magic));
+
+            // The compression is detected so a database without one loads it
+            let mut reloaded = Database::with_storage(storage.clone());
+            reloaded.load().unwrap();
+            assert_eq!(reloaded.get::<serde_json::Value>("user").unwrap(), tree);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "zstd"))]
+    fn compression_without_its_feature() {
+        let mut zstd = MemoryStorage::new();
+        zstd.write_snapshot(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]).unwrap();
+
+        let mut db = Database::with_storage(zstd);
+        assert!(matches!(db.load(), Err(Error::Encoding { format: "zstd", .. })));
+    }
+
+    /// Open an encrypted database that never folds its journal on its own
+    #[cfg(feature = "encryption")]
+    fn open_encrypted(path: &str, key: &[u8; 32]) -> Result<Database> {
+        let mut db = Database::open_encrypted(path, key);
+        db.enable_journal(0);
+
+        return db.load().map(|_| db);
+    }
+
+    /// An encrypted database with a snapshot and three records in its journal, and the lines of that journal
+    #[cfg(feature = "encryption")]
+    fn encrypted_journal(name: &str) -> (String, Vec<String>) {
+        let path = temp_db(name);
+
+        let db = open_encrypted(&path, &[1; 32]).unwrap();
+        db.insert("name", "alice").unwrap();
+        db.compact().unwrap();
+        db.insert("city", "Oslo").unwrap();
+        db.insert("zip", "0150").unwrap();
+        db.insert("country", "NO").unwrap();
+        drop(db);
+
+        let lines = fs::read_to_string(journal::path(Path::new(&path))).unwrap().lines().map(str::to_string).collect();
+
+        return (path, lines);
+    }
+
+    /// Replace the journal of an encrypted database with `lines` and open it
+    #[cfg(feature = "encryption")]
+    fn with_journal(path: &str, lines: &[&String]) -> Result<Database> {
+        let records = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+        fs::write(journal::path(Path::new(path)), records).unwrap();
+
+        return open_encrypted(path, &[1; 32]);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_database() {
+        let path = temp_db("encrypted");
+
+        let db = open_encrypted(&path, &[1; 32]).unwrap();
+        db.insert("ssn", "123-45-6789").unwrap();
+        db.compact().unwrap();
+        db.insert("card", "4111").unwrap();
+        drop(db);
+
+        // Neither the file nor the journal give anything away
+        assert!(!fs::read_to_string(journal::path(Path::new(&path))).unwrap().contains("4111"));
+        assert!(!fs::read(&path).unwrap().windows(3).any(|window| window == b"123"));
+
+        let db = open_encrypted(&path, &[1; 32]).unwrap();
+        assert_eq!(db.get::<String>("ssn").unwrap(), "123-45-6789");
+        assert_eq!(db.get::<String>("card").unwrap(), "4111");
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encryption_wrong_key() {
+        let path = temp_db("encrypted-wrong-key");
+        open_encrypted(&path, &[1; 32]).unwrap().insert("ssn", "123-45-6789").unwrap();
+
+        assert!(matches!(open_encrypted(&path, &[2; 32]), Err(Error::Decryption(_))));
+
+        let mut plain = Database::new(&path);
+        assert!(matches!(plain.load(), Err(Error::Decryption(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encryption_rekey() {
+        let path = temp_db("encrypted-rekey");
+
+        let mut db = open_encrypted(&path, &[1; 32]).unwrap();
+        db.insert("ssn", "123-45-6789").unwrap();
+        db.rekey(&[2; 32]).unwrap();
+        db.insert("name", "alice").unwrap();
+        drop(db);
+
+        assert!(matches!(open_encrypted(&path, &[1; 32]), Err(Error::Decryption(_))));
+        assert_eq!(open_encrypted(&path, &[2; 32]).unwrap().keys().collect::<Vec<_>>(), vec!["name", "ssn"]);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encryption_tampered_snapshot() {
+        let path = temp_db("encrypted-tampered");
+
+        let db = open_encrypted(&path, &[1; 32]).unwrap();
+        db.insert("ssn", "123-45-6789").unwrap();
+        db.compact().unwrap();
+        drop(db);
+
+        // A changed byte anywhere in the sealed data is caught
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, &data).unwrap();
+
+        assert!(matches!(open_encrypted(&path, &[1; 32]), Err(Error::Decryption(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_journal_order() {
+        let (path, lines) = encrypted_journal("encrypted-order");
+        assert_eq!(lines.len(), 3);
+
+        // Records only open at their place in the journal of their snapshot
+        assert!(matches!(with_journal(&path, &[&lines[0], &lines[2]]), Err(Error::Decryption(_))));
+        assert!(matches!(with_journal(&path, &[&lines[1], &lines[0], &lines[2]]), Err(Error::Decryption(_))));
+        assert!(matches!(with_journal(&path, &[&lines[0], &lines[1], &lines[1], &lines[2]]), Err(Error::Decryption(_))));
+
+        let db = with_journal(&path, &lines.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(db.get::<String>("country").unwrap(), "NO");
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_journal_after_crash() {
+        let (path, lines) = encrypted_journal("encrypted-crash");
+        let lines: Vec<&String> = lines.iter().collect();
+
+        // A crash between saving the snapshot and clearing the journal leaves the records of the snapshot before
+        let db = with_journal(&path, &lines).unwrap();
+        db.compact().unwrap();
+        drop(db);
+
+        let db = with_journal(&path, &lines).unwrap();
+        db.insert("planet", "Earth").unwrap();
+        drop(db);
+
+        let db = open_encrypted(&path, &[1; 32]).unwrap();
+        assert_eq!(db.get::<String>("zip").unwrap(), "0150");
+        assert_eq!(db.get::<String>("planet").unwrap(), "Earth");
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_journal_of_older_snapshot() {
+        let (path, lines) = encrypted_journal("encrypted-older");
+        let lines: Vec<&String> = lines.iter().collect();
+
+        // The records of the snapshot before the last one are fine, but not the ones of older snapshots
+        let db = with_journal(&path, &lines).unwrap();
+        db.compact().unwrap();
+        db.compact().unwrap();
+        drop(db);
+
+        assert!(matches!(with_journal(&path, &lines), Err(Error::Decryption(_))));
+    }
+
+    #[bench]
+    fn create_speed(b: &mut test::Bencher) {
+        let path = temp_db("create-speed");
+
+        b.iter(|| {
+            let mut db = Database::new(&path);
+
+            db.load().unwrap();
+        });
+    }
+
+    #[bench]
+    fn insert_speed(b: &mut test::Bencher) {
+        let path = temp_db("basic-operations");
+
+        b.iter(|| {
+            let mut db = Database::new(&path);
+
+            db.load().unwrap();
+
+            db.insert("foo", "bar").unwrap();
+        });
+    }
+
+    /// A database with 1000 small trees for the read benchmarks
+    fn bench_db(name: &str) -> Database {
+        let mut db = Database::new(&temp_db(name));
+        db.load().unwrap();
+
+        let mut batch = WriteBatch::new();
+
+        for i in 0..1000 {
+            batch.insert(&format!("user-{}", i), &serde_json::json!({ "name": "alice", "age": i })).unwrap();
+        }
+
+        db.apply_batch(batch).unwrap();
+
+        return db;
+    }
+
+    #[bench]
+    fn find_speed(b: &mut test::Bencher) {
+        let db = bench_db("find_speed");
+
+        b.iter(|| {
+            for i in 0..1000 {
+                test::black_box(db.find(&format!("user-{}", i)).unwrap());
+            }
+        });
+    }
+
+    #[bench]
+    fn parallel_find_speed(b: &mut test::Bencher) {
+        let db = bench_db("parallel_find_speed");
+
+        // 8 threads do the work of `find_speed` each
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                for _ in 0..8 {
+                    scope.spawn(|| {
+                        for i in 0..1000 {
+                            test::black_box(db.find(&format!("user-{}", i)).unwrap());
+                        }
+                    });
+                }
+            });
+        });
+    }
+
+    #[bench]
+    fn parallel_find_while_writing_speed(b: &mut test::Bencher) {
+        let mut db = Database::new(&temp_db("parallel_find_while_writing_speed"));
+        db.enable_journal(0);
+        db.load().unwrap();
+
+        for i in 0..1000 {
+            db.insert(&format!("user-{}", i), &i).unwrap();
+        }
+
+        // 7 readers and one writer that appends to the journal
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    for i in 0..100 {
+                        db.insert(&format!("user-{}", i), &i).unwrap();
+                    }
+                });
+
+                for _ in 0..7 {
+                    scope.spawn(|| {
+                        for i in 0..1000 {
+                            test::black_box(db.find(&format!("user-{}", i)).unwrap());
+                        }
+                    });
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,66 @@
+//! Advisory locks that keep other processes from opening the same database
+//!
+//! The database file itself is replaced on every save so the lock is taken on
+//! a `<path>.lock` file next to it that stays around. A read write database takes
+//! an exclusive lock and a read only database takes a shared lock.
+
+use std::fs::{ File, OpenOptions, TryLockError };
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate::{ Error, Result };
+
+/// How long to sleep between tries while waiting for a lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The path of the lock file that belongs to the database at `path`
+pub(crate) fn path(path: &Path) -> PathBuf {
+    let mut lock = path.as_os_str().to_owned();
+    lock.push(".lock");
+
+    return PathBuf::from(lock);
+}
+
+/// Lock the database at `path`. The lock is held until the returned file is closed.
+/// When another process holds the lock we wait up to `timeout` for it before giving up.
+/// A shared lock only opens the lock file for reading so read only databases work on read only media.
+/// It is never created there, so without a lock file no writer ever opened the database and there is nothing to lock
+pub(crate) fn acquire(path: &Path, shared: bool, timeout: Option<Duration>) -> Result<Option<File>> {
+    let lock_path = self::path(path);
+
+    let file = if shared {
+        match File::open(&lock_path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(Error::Io(error))
+        }
+    } else {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+        let result = if shared { file.try_lock_shared() } else { file.try_lock() };
+
+        match result {
+            Ok(()) => return Ok(Some(file)),
+
+            Err(TryLockError::WouldBlock) => {
+                match deadline {
+                    Some(deadline) if Instant::now() < deadline => thread::sleep(RETRY_INTERVAL),
+                    _ => return Err(Error::Locked(path.display().to_string()))
+                }
+            },
+
+            Err(TryLockError::Error(error)) => return Err(Error::Io(error))
+        }
+    }
+}
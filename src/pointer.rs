@@ -0,0 +1,168 @@
+//! Paths into nested trees in the json pointer syntax of RFC 6901
+//!
+//! A path like `/users/alice/age` points at the `age` key of the `alice` tree in the `users` tree.
+//! Arrays are indexed by number and `-` points just past the end of an array.
+//! A `/` inside of a key is written as `~1` and a `~` is written as `~0`.
+//! The leading `/` is optional so `users/alice/age` works too.
+
+use crate::{ Error, Result };
+
+/// Split a path into its unescaped keys
+/// The empty path points at the whole tree
+pub(crate) fn parse(path: &str) -> Result<Vec<String>> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    return path.strip_prefix('/').unwrap_or(path)
+        .split('/')
+        .map(|token| unescape(token).ok_or_else(|| Error::InvalidPath(path.to_string())))
+        .collect();
+}
+
+/// Join keys back into a path
+pub(crate) fn format(tokens: &[String]) -> String {
+    return tokens.iter().map(|token| format!("/{}", token.replace('~', "~0").replace('/', "~1"))).collect();
+}
+
+fn unescape(token: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(token.len());
+    let mut chars = token.chars();
+
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '~' => match chars.next()? {
+                '0' => '~',
+                '1' => '/',
+                _ => return None
+            },
+
+            c => c
+        });
+    }
+
+    return Some(unescaped);
+}
+
+/// The index of an existing item in an array
+/// Leading zeros are not allowed by the RFC
+fn index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) || !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    return token.parse().ok();
+}
+
+/// The index an array item is written to. `-` and the length of the array both append
+fn slot(token: &str, len: usize, tokens: &[String]) -> Result<usize> {
+    return match token {
+        "-" => Ok(len),
+        token => index(token).filter(|index| *index <= len).ok_or_else(|| Error::InvalidPath(format(tokens)))
+    }
+}
+
+/// The value at the path
+pub(crate) fn get<'a>(root: &'a serde_json::Value, tokens: &[String]) -> Option<&'a serde_json::Value> {
+    return tokens.iter().try_fold(root, |value, token| {
+        match value {
+            serde_json::Value::Object(map) => map.get(token),
+            serde_json::Value::Array(array) => index(token).and_then(|index| array.get(index)),
+            _ => None
+        }
+    });
+}
+
+/// Set the value at the path and return the value that was there before
+/// Trees that do not exist on the way are created
+pub(crate) fn set(root: &mut serde_json::Value, tokens: &[String], value: serde_json::Value) -> Result<Option<serde_json::Value>> {
+    let (last, parents) = tokens.split_last().ok_or_else(|| Error::InvalidPath(String::new()))?;
+    let mut current = root;
+
+    // Errors can only come up while walking values that already exist
+    // so nothing has been created yet when we bail out
+    for token in parents {
+        current = match current {
+            serde_json::Value::Object(map) => {
+                map.entry(token.clone()).or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()))
+            },
+
+            serde_json::Value::Array(array) => {
+                let slot = slot(token, array.len(), tokens)?;
+
+                if slot == array.len() {
+                    array.push(serde_json::Value::Object(serde_json::Map::new()));
+                }
+
+                &mut array[slot]
+            },
+
+            other => return Err(Error::mismatch("tree", other))
+        };
+    }
+
+    return match current {
+        serde_json::Value::Object(map) => Ok(map.insert(last.clone(), value)),
+
+        serde_json::Value::Array(array) => {
+            let slot = slot(last, array.len(), tokens)?;
+
+            if slot == array.len() {
+                array.push(value);
+
+                Ok(None)
+            } else {
+                Ok(Some(std::mem::replace(&mut array[slot], value)))
+            }
+        },
+
+        other => Err(Error::mismatch("tree", other))
+    }
+}
+
+/// Remove the value at the path and return it
+/// Removing a path that does not exist does nothing
+pub(crate) fn remove(root: &mut serde_json::Value, tokens: &[String]) -> Result<Option<serde_json::Value>> {
+    let (last, parents) = tokens.split_last().ok_or_else(|| Error::InvalidPath(String::new()))?;
+    let mut current = root;
+
+    for token in parents {
+        let next = match current {
+            serde_json::Value::Object(map) => map.get_mut(token),
+            serde_json::Value::Array(array) => index(token).and_then(move |index| array.get_mut(index)),
+            _ => None
+        };
+
+        current = match next {
+            Some(next) => next,
+            None => return Ok(None)
+        };
+    }
+
+    return Ok(match current {
+        serde_json::Value::Object(map) => map.remove(last),
+        serde_json::Value::Array(array) => index(last).filter(|index| *index < array.len()).map(|index| array.remove(index)),
+        _ => None
+    });
+}
+
+/// The part of the path that a change to it can touch in `root`.
+/// This is the first array on the way (changes inside of it shift its items around),
+/// the first key that does not exist yet (it is created with everything below it)
+/// or else the whole path
+pub(crate) fn scope<'a>(root: &serde_json::Value, tokens: &'a [String]) -> &'a [String] {
+    let mut current = root;
+
+    for (depth, token) in tokens.iter().enumerate() {
+        current = match current {
+            serde_json::Value::Object(map) => match map.get(token) {
+                Some(value) => value,
+                None => return &tokens[..=depth]
+            },
+
+            _ => return &tokens[..depth]
+        };
+    }
+
+    return tokens;
+}
@@ -0,0 +1,390 @@
+//! Finding the entries of a table that match some conditions
+//!
+//! A [Query] is built with [Database::query](crate::Database::query) or [Table::query](crate::Table::query)
+//! and runs over the top level entries of the table. Fields are paths into every entry so
+//! `address/city` looks at the `city` key of the `address` tree of each entry.
+
+use std::cmp::Ordering;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{ pointer, Error, Result, Table, Value };
+
+/// The direction to sort the results of a [Query] in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc
+}
+
+/// What a field has to be for an entry to match
+enum Condition {
+    Eq(serde_json::Value),
+    Ne(serde_json::Value),
+    Lt(serde_json::Value),
+    Gt(serde_json::Value),
+    In(Vec<serde_json::Value>),
+    Exists(bool),
+    Regex(Regex),
+    Contains(serde_json::Value)
+}
+
+impl Condition {
+    fn parse(op: &str, value: &serde_json::Value) -> Result<Condition> {
+        return Ok(match op {
+            "$eq" => Condition::Eq(value.clone()),
+            "$ne" => Condition::Ne(value.clone()),
+            "$lt" => Condition::Lt(value.clone()),
+            "$gt" => Condition::Gt(value.clone()),
+            "$in" => Condition::In(value.as_array().ok_or_else(|| Error::mismatch("array", value))?.clone()),
+            "$exists" => Condition::Exists(value.as_bool().ok_or_else(|| Error::mismatch("bool", value))?),
+            "$contains" => Condition::Contains(value.clone()),
+
+            "$regex" => {
+                let pattern = value.as_str().ok_or_else(|| Error::mismatch("string", value))?;
+
+                Condition::Regex(Regex::new(pattern).map_err(|error| Error::InvalidQuery(error.to_string()))?)
+            },
+
+            _ => return Err(Error::InvalidQuery(format!("unknown operator `{}`", op)))
+        });
+    }
+
+    /// Check the value of a field against the condition. The value is [None] when the entry does not have the field
+    fn matches(&self, value: Option<&serde_json::Value>) -> bool {
+        return match (self, value) {
+            (Condition::Exists(exists), value) => *exists == value.is_some(),
+            (Condition::Ne(other), value) => !value.is_some_and(|value| equal(value, other)),
+            (_, None) => false,
+
+            (Condition::Eq(other), Some(value)) => equal(value, other),
+            (Condition::Lt(other), Some(value)) => compare(value, other) == Some(Ordering::Less),
+            (Condition::Gt(other), Some(value)) => compare(value, other) == Some(Ordering::Greater),
+            (Condition::In(others), Some(value)) => others.iter().any(|other| equal(value, other)),
+            (Condition::Regex(regex), Some(value)) => value.as_str().is_some_and(|value| regex.is_match(value)),
+
+            (Condition::Contains(other), Some(value)) => match (value, other) {
+                (serde_json::Value::String(value), serde_json::Value::String(other)) => value.contains(other.as_str()),
+                (serde_json::Value::Array(values), other) => values.iter().any(|value| equal(value, other)),
+                _ => false
+            }
+        }
+    }
+}
+
+/// Compare two values of the same type. Numbers are compared by their value so `30` and `30.0` are the same
+pub(crate) fn compare(a: &serde_json::Value, b: &serde_json::Value) -> Option<Ordering> {
+    return match (a, b) {
+        (serde_json::Value::Number(a), serde_json::Value::Number(b)) => match (integer(a), integer(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?)
+        },
+        (serde_json::Value::String(a), serde_json::Value::String(b)) => Some(a.cmp(b)),
+        (serde_json::Value::Bool(a), serde_json::Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None
+    }
+}
+
+/// The value of a number that is an integer, wide enough for any [i64] and [u64]
+pub(crate) fn integer(number: &serde_json::Number) -> Option<i128> {
+    return number.as_i64().map(i128::from).or_else(|| number.as_u64().map(i128::from));
+}
+
+pub(crate) fn equal(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    return compare(a, b).map_or_else(|| a == b, |ordering| ordering == Ordering::Equal);
+}
+
+/// The order of values of any type for sorting. Missing fields come first,
+/// then null, bools, numbers, strings, arrays and trees
+fn sort_order(a: Option<&serde_json::Value>, b: Option<&serde_json::Value>) -> Ordering {
+    fn rank(value: Option<&serde_json::Value>) -> u8 {
+        return match value {
+            None => 0,
+            Some(serde_json::Value::Null) => 1,
+            Some(serde_json::Value::Bool(_)) => 2,
+            Some(serde_json::Value::Number(_)) => 3,
+            Some(serde_json::Value::String(_)) => 4,
+            Some(serde_json::Value::Array(_)) => 5,
+            Some(serde_json::Value::Object(_)) => 6
+        }
+    }
+
+    return match (a, b) {
+        (Some(a), Some(b)) => compare(a, b).unwrap_or_else(|| rank(Some(a)).cmp(&rank(Some(b)))),
+        _ => rank(a).cmp(&rank(b))
+    }
+}
+
+/// Conditions on the fields of an entry that all have to match
+#[derive(Default)]
+pub(crate) struct Filter {
+    conditions: Vec<(Vec<String>, Condition)>
+}
+
+impl Filter {
+    /// Parse a filter in the mongo style like `{ "name": "alice", "age": { "$gt": 30 } }`.
+    /// A field with a plain value has to be equal to it. The operators are
+    /// `$eq`, `$ne`, `$lt`, `$gt`, `$in`, `$exists`, `$regex` and `$contains`
+    pub(crate) fn parse(filter: &serde_json::Value) -> Result<Filter> {
+        let fields = filter.as_object().ok_or_else(|| Error::mismatch("tree", filter))?;
+        let mut conditions = Vec::new();
+
+        for (field, value) in fields {
+            let path = pointer::parse(field)?;
+
+            match value.as_object() {
+                Some(ops) if !ops.is_empty() && ops.keys().all(|op| op.starts_with('$')) => {
+                    for (op, value) in ops {
+                        conditions.push((path.clone(), Condition::parse(op, value)?));
+                    }
+                },
+
+                _ => conditions.push((path, Condition::Eq(value.clone())))
+            }
+        }
+
+        return Ok(Filter { conditions });
+    }
+
+    /// Check if an entry matches all of the conditions
+    pub(crate) fn matches(&self, value: &serde_json::Value) -> bool {
+        return self.conditions.iter().all(|(path, condition)| condition.matches(pointer::get(value, path)));
+    }
+
+    /// The fields that the filter wants to be equal to a value, for the document that an upsert inserts
+    pub(crate) fn equalities(&self) -> impl Iterator<Item = (&Vec<String>, &serde_json::Value)> {
+        return self.conditions.iter().filter_map(|(path, condition)| match condition {
+            Condition::Eq(value) => Some((path, value)),
+            _ => None
+        });
+    }
+}
+
+/// A query over the entries of a table
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # let db = Database::in_memory();
+/// # db.set_path("alice", &serde_json::json!({ "age": 35, "active": true })).unwrap();
+/// # db.set_path("bob", &serde_json::json!({ "age": 25, "active": true })).unwrap();
+/// let users = db.query()
+///     .where_("age").gt(30)
+///     .and("active").eq(true)
+///     .sort("age", Order::Desc)
+///     .limit(10)
+///     .run()
+///     .unwrap();
+///
+/// for (key, user) in users {
+///     println!("{} = {}", key, user);
+/// }
+/// ```
+pub struct Query<'a> {
+    table: Table<'a>,
+
+    filter: Filter,
+
+    sort: Vec<(Vec<String>, Order)>,
+    skip: usize,
+    limit: Option<usize>,
+
+    /// The fields to keep in the results or [None] to keep the whole entries
+    fields: Option<Vec<Vec<String>>>,
+
+    /// The first thing that went wrong while building the query. It is returned by [Query::run]
+    error: Option<Error>
+}
+
+/// A field of a [Query] that is waiting for its condition
+pub struct Where<'a> {
+    query: Query<'a>,
+    field: String
+}
+
+impl<'a> Query<'a> {
+    pub(crate) fn new(table: Table<'a>) -> Query<'a> {
+        return Query {
+            table,
+            filter: Filter::default(),
+            sort: Vec::new(),
+            skip: 0,
+            limit: None,
+            fields: None,
+            error: None
+        }
+    }
+
+    /// Start a condition on a field like `age` or `address/city`
+    pub fn where_(self, field: &str) -> Where<'a> {
+        return Where {
+            query: self,
+            field: field.to_string()
+        }
+    }
+
+    /// Add another condition. Entries have to match all of the conditions
+    pub fn and(self, field: &str) -> Where<'a> {
+        return self.where_(field);
+    }
+
+    /// Sort the results by a field. Sorting by more fields uses the later ones to break ties
+    /// Without any sort the results come out in key order
+    pub fn sort(mut self, field: &str, order: Order) -> Query<'a> {
+        if let Some(path) = self.path(field) {
+            self.sort.push((path, order));
+        }
+
+        return self;
+    }
+
+    /// Skip the first `skip` results
+    pub fn skip(mut self, skip: usize) -> Query<'a> {
+        self.skip = skip;
+
+        return self;
+    }
+
+    /// Return at most `limit` results
+    pub fn limit(mut self, limit: usize) -> Query<'a> {
+        self.limit = Some(limit);
+
+        return self;
+    }
+
+    /// Only keep these fields in the results. Fields that an entry does not have are left out
+    pub fn select(mut self, fields: &[&str]) -> Query<'a> {
+        let paths = fields.iter().filter_map(|field| self.path(field)).collect();
+        self.fields = Some(paths);
+
+        return self;
+    }
+
+    /// Run the query and return the `(key, value)` pairs of the matching entries
+    pub fn run(self) -> Result<Vec<(String, Value)>> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut entries = self.table.filter(|value| self.filter.matches(value))?;
+
+        entries.sort_by(|(a_key, a), (b_key, b)| {
+            return self.sort.iter()
+                .map(|(path, order)| {
+                    let ordering = sort_order(pointer::get(a, path), pointer::get(b, path));
+
+                    return if *order == Order::Desc { ordering.reverse() } else { ordering };
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or_else(|| a_key.cmp(b_key));
+        });
+
+        let entries = entries.into_iter()
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX));
+
+        return Ok(entries.map(|(key, value)| {
+            let value = match &self.fields {
+                Some(fields) => project(&value, fields),
+                None => value
+            };
+
+            return (key, Value::from(value));
+        }).collect());
+    }
+
+    /// Return the amount of entries that match the conditions, ignoring skip and limit
+    pub fn count(self) -> Result<usize> {
+        return self.skip(0).limit(usize::MAX).run().map(|entries| entries.len());
+    }
+
+    /// Parse the path of a field and remember the error for [Query::run] when it is not valid
+    fn path(&mut self, field: &str) -> Option<Vec<String>> {
+        return self.check(pointer::parse(field));
+    }
+
+    fn check<T>(&mut self, result: Result<T>) -> Option<T> {
+        return match result {
+            Ok(value) => Some(value),
+
+            Err(error) => {
+                self.error.get_or_insert(error);
+
+                None
+            }
+        }
+    }
+}
+
+impl<'a> Where<'a> {
+    fn condition(self, condition: Result<Condition>) -> Query<'a> {
+        let mut query = self.query;
+
+        if let Some(path) = query.path(&self.field) {
+            if let Some(condition) = query.check(condition) {
+                query.filter.conditions.push((path, condition));
+            }
+        }
+
+        return query;
+    }
+
+    fn value<T: Serialize>(value: T) -> Result<serde_json::Value> {
+        return Ok(serde_json::to_value(value)?);
+    }
+
+    /// The field is equal to `value`
+    pub fn eq<T: Serialize>(self, value: T) -> Query<'a> {
+        return self.condition(Self::value(value).map(Condition::Eq));
+    }
+
+    /// The field is missing or not equal to `value`
+    pub fn ne<T: Serialize>(self, value: T) -> Query<'a> {
+        return self.condition(Self::value(value).map(Condition::Ne));
+    }
+
+    /// The field is less than `value`. Only numbers, strings and bools of the same type are compared
+    pub fn lt<T: Serialize>(self, value: T) -> Query<'a> {
+        return self.condition(Self::value(value).map(Condition::Lt));
+    }
+
+    /// The field is greater than `value`. Only numbers, strings and bools of the same type are compared
+    pub fn gt<T: Serialize>(self, value: T) -> Query<'a> {
+        return self.condition(Self::value(value).map(Condition::Gt));
+    }
+
+    /// The field is equal to one of `values`
+    pub fn in_<T: Serialize>(self, values: &[T]) -> Query<'a> {
+        return self.condition(values.iter().map(Self::value).collect::<Result<Vec<_>>>().map(Condition::In));
+    }
+
+    /// The entry has the field when `exists` is true and does not have it when it is false
+    pub fn exists(self, exists: bool) -> Query<'a> {
+        return self.condition(Ok(Condition::Exists(exists)));
+    }
+
+    /// The field is a string that matches the regular expression `pattern`
+    pub fn regex(self, pattern: &str) -> Query<'a> {
+        let regex = Regex::new(pattern).map_err(|error| Error::InvalidQuery(error.to_string()));
+
+        return self.condition(regex.map(Condition::Regex));
+    }
+
+    /// The field is a string that contains the string `value` or an array that contains `value`
+    pub fn contains<T: Serialize>(self, value: T) -> Query<'a> {
+        return self.condition(Self::value(value).map(Condition::Contains));
+    }
+}
+
+/// A copy of `value` with only the fields at `paths`
+fn project(value: &serde_json::Value, paths: &[Vec<String>]) -> serde_json::Value {
+    let mut projected = serde_json::json!({});
+
+    for path in paths {
+        if let Some(field) = pointer::get(value, path) {
+            let _ = pointer::set(&mut projected, path, field.clone());
+        }
+    }
+
+    return projected;
+}
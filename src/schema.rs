@@ -0,0 +1,344 @@
+//! Checking the entries of a table against a schema before a change is saved
+//!
+//! Schemas are json schemas with the keywords `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `minimum`, `maximum`, `minLength`, `maxLength`, `minItems`,
+//! `maxItems` and `pattern`. Other keywords are ignored. The schema of a table is saved in the
+//! `$meta` key of the main tree and every entry of the table has to match it.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::doc;
+use crate::error::type_name;
+use crate::journal::Record;
+use crate::table::{ self, touched, Touched, TABLES };
+use crate::{ pointer, Error, Result };
+
+/// The path in the main tree of the schema of a table. The default table is [None]
+pub(crate) fn schema_path(table: Option<&str>) -> Vec<String> {
+    let mut path = doc::meta_path(table);
+    path.push("schema".to_string());
+
+    return path;
+}
+
+/// A schema that the entries of a table have to match. It is set with [Table::set_schema](crate::Table::set_schema)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    pub(crate) schema: serde_json::Value
+}
+
+impl Schema {
+    /// Use a json schema. Returns [Error::InvalidSchema] when it cannot be used
+    pub fn json(schema: serde_json::Value) -> Result<Schema> {
+        check(&schema, "")?;
+
+        return Ok(Schema { schema });
+    }
+
+    /// A schema for entries that are trees with exactly these fields. The types are
+    /// `string`, `number`, `integer`, `bool`, `tree`, `array`, `null` and `any`.
+    /// A type that ends with `?` makes the field optional
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// let schema = Schema::fields(&[("name", "string"), ("age", "integer?")]).unwrap();
+    /// ```
+    pub fn fields(fields: &[(&str, &str)]) -> Result<Schema> {
+        let mut properties = serde_json::Map::new();
+        let mut required = Vec::new();
+
+        for (field, kind) in fields {
+            let (kind, optional) = match kind.strip_suffix('?') {
+                Some(kind) => (kind, true),
+                None => (*kind, false)
+            };
+
+            let property = match kind {
+                "string" | "number" | "integer" | "array" | "null" => serde_json::json!({ "type": kind }),
+                "bool" => serde_json::json!({ "type": "boolean" }),
+                "tree" => serde_json::json!({ "type": "object" }),
+                "any" => serde_json::json!({}),
+                _ => return Err(Error::InvalidSchema(format!("unknown type `{}` for the field `{}`", kind, field)))
+            };
+
+            properties.insert(field.to_string(), property);
+
+            if !optional {
+                required.push(field.to_string());
+            }
+        }
+
+        return Schema::json(serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false
+        }));
+    }
+
+    /// The schema as a json schema
+    pub fn to_json(&self) -> &serde_json::Value {
+        return &self.schema;
+    }
+}
+
+/// The compiled `pattern`s of the schemas, so every pattern is compiled once and not for every string it checks
+#[derive(Default)]
+pub(crate) struct Patterns {
+    regexes: HashMap<String, Regex>
+}
+
+impl Patterns {
+    fn get(&mut self, pattern: &str) -> Result<&Regex> {
+        if !self.regexes.contains_key(pattern) {
+            // Patterns are checked when a schema is made, but a schema in a file could have been edited by hand
+            let regex = Regex::new(pattern).map_err(|error| Error::InvalidSchema(error.to_string()))?;
+
+            self.regexes.insert(pattern.to_string(), regex);
+        }
+
+        return Ok(&self.regexes[pattern]);
+    }
+}
+
+const TYPES: [&str; 7] = ["null", "boolean", "integer", "number", "string", "array", "object"];
+
+/// Make sure a schema can be used. `path` is where it is inside of the outer schema
+fn check(schema: &serde_json::Value, path: &str) -> Result<()> {
+    let invalid = |reason: String| Error::InvalidSchema(format!("{} at `{}`", reason, if path.is_empty() { "/" } else { path }));
+    let schema = schema.as_object().ok_or_else(|| invalid(format!("expected a tree but found a {}", type_name(schema))))?;
+
+    if let Some(kind) = schema.get("type") {
+        let kinds = match kind {
+            serde_json::Value::Array(kinds) => kinds.iter().collect(),
+            kind => vec![kind]
+        };
+
+        for kind in kinds {
+            if !kind.as_str().is_some_and(|kind| TYPES.contains(&kind)) {
+                return Err(invalid(format!("unknown type {}", kind)));
+            }
+        }
+    }
+
+    if let Some(pattern) = schema.get("pattern") {
+        let pattern = pattern.as_str().ok_or_else(|| invalid("`pattern` is not a string".to_string()))?;
+
+        Regex::new(pattern).map_err(|error| invalid(error.to_string()))?;
+    }
+
+    if let Some(properties) = schema.get("properties") {
+        let properties = properties.as_object().ok_or_else(|| invalid("`properties` is not a tree".to_string()))?;
+
+        for (field, property) in properties {
+            check(property, &format!("{}/properties/{}", path, field))?;
+        }
+    }
+
+    for keyword in ["items", "additionalProperties"].iter() {
+        match schema.get(*keyword) {
+            Some(serde_json::Value::Bool(_)) | None => {},
+            Some(inner) => check(inner, &format!("{}/{}", path, keyword))?
+        }
+    }
+
+    return Ok(());
+}
+
+/// The json schema name of the type of a value
+fn json_type(value: &serde_json::Value) -> &'static str {
+    return match value {
+        serde_json::Value::Number(number) if number.as_f64().is_some_and(|number| number.fract() == 0.0) => "integer",
+        serde_json::Value::Object(_) => "object",
+        serde_json::Value::Bool(_) => "boolean",
+        value => type_name(value)
+    }
+}
+
+/// Check a value against a schema. `path` is where the value is, for the error
+fn validate(schema: &serde_json::Value, value: &serde_json::Value, path: &mut Vec<String>, patterns: &mut Patterns) -> Result<()> {
+    let invalid = |path: &[String], reason: String| Error::Invalid { path: pointer::format(path), reason };
+
+    let schema = match schema {
+        serde_json::Value::Object(schema) => schema,
+        serde_json::Value::Bool(false) => return Err(invalid(path, "no value is allowed here".to_string())),
+        _ => return Ok(())
+    };
+
+    if let Some(kind) = schema.get("type") {
+        let kinds: Vec<&str> = match kind {
+            serde_json::Value::Array(kinds) => kinds.iter().filter_map(|kind| kind.as_str()).collect(),
+            kind => kind.as_str().into_iter().collect()
+        };
+
+        let found = json_type(value);
+
+        if !kinds.iter().any(|kind| *kind == found || (*kind == "number" && found == "integer")) {
+            return Err(invalid(path, format!("expected a {} but found a {}", kinds.join(" or "), found)));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|allowed| allowed.as_array()) {
+        if !allowed.contains(value) {
+            return Err(invalid(path, format!("{} is not one of {}", value, serde_json::Value::Array(allowed.clone()))));
+        }
+    }
+
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            return Err(invalid(path, format!("expected {} but found {}", constant, value)));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema.get("minimum").and_then(|minimum| minimum.as_f64()) {
+            if number < minimum {
+                return Err(invalid(path, format!("{} is less than the minimum of {}", value, minimum)));
+            }
+        }
+
+        if let Some(maximum) = schema.get("maximum").and_then(|maximum| maximum.as_f64()) {
+            if number > maximum {
+                return Err(invalid(path, format!("{} is more than the maximum of {}", value, maximum)));
+            }
+        }
+    }
+
+    if let Some(string) = value.as_str() {
+        let length = string.chars().count() as u64;
+
+        if let Some(min) = schema.get("minLength").and_then(|min| min.as_u64()) {
+            if length < min {
+                return Err(invalid(path, format!("the string is shorter than {} characters", min)));
+            }
+        }
+
+        if let Some(max) = schema.get("maxLength").and_then(|max| max.as_u64()) {
+            if length > max {
+                return Err(invalid(path, format!("the string is longer than {} characters", max)));
+            }
+        }
+
+        if let Some(pattern) = schema.get("pattern").and_then(|pattern| pattern.as_str()) {
+            if !patterns.get(pattern)?.is_match(string) {
+                return Err(invalid(path, format!("{} does not match the pattern `{}`", value, pattern)));
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(min) = schema.get("minItems").and_then(|min| min.as_u64()) {
+            if (items.len() as u64) < min {
+                return Err(invalid(path, format!("the array has less than {} items", min)));
+            }
+        }
+
+        if let Some(max) = schema.get("maxItems").and_then(|max| max.as_u64()) {
+            if items.len() as u64 > max {
+                return Err(invalid(path, format!("the array has more than {} items", max)));
+            }
+        }
+
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                validate(item_schema, item, path, patterns)?;
+                path.pop();
+            }
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        let properties = schema.get("properties").and_then(|properties| properties.as_object());
+
+        if let Some(required) = schema.get("required").and_then(|required| required.as_array()) {
+            for field in required.iter().filter_map(|field| field.as_str()) {
+                if !fields.contains_key(field) {
+                    path.push(field.to_string());
+                    return Err(invalid(path, "the field is required but missing".to_string()));
+                }
+            }
+        }
+
+        for (field, inner) in fields {
+            path.push(field.clone());
+
+            match properties.and_then(|properties| properties.get(field)) {
+                Some(property) => validate(property, inner, path, patterns)?,
+
+                None => match schema.get("additionalProperties") {
+                    Some(serde_json::Value::Bool(false)) => return Err(invalid(path, "the field is not in the schema".to_string())),
+                    Some(additional) => validate(additional, inner, path, patterns)?,
+                    None => {}
+                }
+            }
+
+            path.pop();
+        }
+    }
+
+    return Ok(());
+}
+
+/// Check the entries that `records` touched in `root` against the schemas of their tables.
+/// The path in the error is the path inside of the table
+pub(crate) fn check_changes(root: &serde_json::Value, records: &[Record], patterns: &mut Patterns) -> Result<()> {
+    for record in records {
+        match touched(record.path()) {
+            Touched::Entry(table, key) => {
+                if let (Some(schema), Some(entry)) = (schema_of(root, table.as_deref()), table::entry(root, table.as_deref(), &key)) {
+                    validate(schema, entry, &mut vec![key], patterns)?;
+                }
+            },
+
+            Touched::Table(name) => check_table(root, Some(&name), patterns)?,
+            Touched::Tables => check_all(root, patterns)?,
+
+            // A new schema has to fit the entries that are there already
+            Touched::Meta => match record.path() {
+                [_] => check_all(root, patterns)?,
+                [_, tables] if tables == "tables" => check_all(root, patterns)?,
+                [_, default] | [_, default, ..] if default == "default" && is_schema(&record.path()[2..]) => check_table(root, None, patterns)?,
+                [_, tables, name] | [_, tables, name, ..] if tables == "tables" && is_schema(&record.path()[3..]) => check_table(root, Some(name), patterns)?,
+                _ => {}
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// Check if the rest of a path in the metadata of a table can change its schema
+fn is_schema(rest: &[String]) -> bool {
+    return rest.first().is_none_or(|first| first == "schema");
+}
+
+fn schema_of<'r>(root: &'r serde_json::Value, table: Option<&str>) -> Option<&'r serde_json::Value> {
+    return pointer::get(root, &schema_path(table));
+}
+
+/// Check every entry of a table against its schema
+fn check_table(root: &serde_json::Value, table: Option<&str>, patterns: &mut Patterns) -> Result<()> {
+    if let Some(schema) = schema_of(root, table) {
+        for (key, entry) in table::entries(root, table) {
+            validate(schema, entry, &mut vec![key.clone()], patterns)?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// Check every entry of every table that has a schema
+fn check_all(root: &serde_json::Value, patterns: &mut Patterns) -> Result<()> {
+    check_table(root, None, patterns)?;
+
+    if let Some(named) = root.get(TABLES).and_then(|tables| tables.as_object()) {
+        for name in named.keys() {
+            check_table(root, Some(name), patterns)?;
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,284 @@
+//! Where a [Database](crate::Database) keeps its data
+//!
+//! A storage holds a snapshot of the whole main tree and the records of the journal that
+//! were appended since the snapshot was written. The database decides what goes into them,
+//! a storage only has to keep the bytes safe. [FileStorage] keeps them in files next to each
+//! other and [MemoryStorage] keeps them in memory for tests. Use your own backend with
+//! [Database::with_storage](crate::Database::with_storage).
+
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ self, Read, Seek, SeekFrom, Write };
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+
+use crate::{ journal, lock, Result };
+
+/// The place a [Database](crate::Database) reads its data from and saves it to
+pub trait Storage: Send {
+    /// Keep other users of the storage away until the storage is dropped. A read only database
+    /// asks for a `shared` lock. When someone else holds the lock wait up to `timeout` before
+    /// returning [Error::Locked](crate::Error::Locked). This is called on every load, so taking a lock twice has to work
+    fn lock(&mut self, shared: bool, timeout: Option<Duration>) -> Result<()>;
+
+    /// Read the last snapshot that was written or [None] when there is none yet
+    fn read_snapshot(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// Replace the snapshot. This has to be all or nothing, so after a crash the old or the new snapshot is there
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> Result<()>;
+
+    /// Read the records that were appended since the records were last cleared, oldest first.
+    /// A record that was not appended completely because of a crash is left out
+    fn read_records(&mut self) -> Result<Vec<Vec<u8>>>;
+
+    /// Append a record. It has to survive a crash once this returns
+    fn append_record(&mut self, record: &[u8]) -> Result<()>;
+
+    /// Throw away every record once they are part of the snapshot
+    fn clear_records(&mut self) -> Result<()>;
+}
+
+/// The storage of [Database::new](crate::Database::new). The snapshot is a file, the records
+/// go on their own lines in `<path>.journal` and the lock is taken on `<path>.lock`
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+
+    /// The lock file. The lock is held for as long as this is open.
+    /// A read only storage has none when there is no lock file to take a shared lock on
+    lock: Option<File>,
+
+    /// The storage is only read from
+    read_only: bool,
+
+    /// The journal once it is opened for appending
+    journal: Option<File>
+}
+
+impl FileStorage {
+    /// A storage for the database file at `path`
+    pub fn new(path: &str) -> FileStorage {
+        return FileStorage {
+            path: PathBuf::from(path),
+            lock: None,
+            read_only: false,
+            journal: None
+        }
+    }
+
+    /// Open the journal for appending and cut off a record at the end that was not appended completely
+    fn open_journal(&mut self, valid: Option<u64>) -> io::Result<&mut File> {
+        if self.journal.is_none() {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(journal::path(&self.path))?;
+
+            if let Some(valid) = valid.filter(|valid| *valid != file.metadata().map_or(0, |metadata| metadata.len())) {
+                file.set_len(valid)?;
+                file.sync_all()?;
+            }
+
+            self.journal = Some(file);
+        }
+
+        return Ok(self.journal.as_mut().unwrap());
+    }
+}
+
+impl Storage for FileStorage {
+    fn lock(&mut self, shared: bool, timeout: Option<Duration>) -> Result<()> {
+        if self.lock.is_none() {
+            self.lock = lock::acquire(&self.path, shared, timeout)?;
+        }
+
+        self.read_only = shared;
+
+        return Ok(());
+    }
+
+    /// A database that is not read only creates an empty file here when there is none
+    fn read_snapshot(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(!self.read_only)
+            .create(!self.read_only)
+            .truncate(false)
+            .open(&self.path)?;
+
+        let mut snapshot = Vec::new();
+        file.read_to_end(&mut snapshot)?;
+
+        return Ok(Some(snapshot).filter(|snapshot| !snapshot.is_empty()));
+    }
+
+    /// Write the snapshot to a temporary file next to the database, sync it and then rename it over the database file
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> Result<()> {
+        let tmp = tmp_path(&self.path);
+
+        {
+            let mut tmp_file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp)?;
+
+            tmp_file.write_all(snapshot)?;
+            tmp_file.sync_all()?;
+        }
+
+        fs::rename(&tmp, &self.path)?;
+        sync_dir(&self.path)?;
+
+        return Ok(());
+    }
+
+    fn read_records(&mut self) -> Result<Vec<Vec<u8>>> {
+        let path = journal::path(&self.path);
+
+        let mut data = Vec::new();
+
+        match File::open(&path) {
+            Ok(mut file) => { file.read_to_end(&mut data)?; },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into())
+        }
+
+        // Everything after the last new line is a record that was torn by a crash
+        let valid = data.iter().rposition(|byte| *byte == b'\n').map_or(0, |end| end + 1);
+
+        let records = match valid {
+            0 => Vec::new(),
+            valid => data[..valid - 1].split(|byte| *byte == b'\n').map(|record| record.to_vec()).collect()
+        };
+
+        if !self.read_only {
+            self.open_journal(Some(valid as u64))?;
+        }
+
+        return Ok(records);
+    }
+
+    fn append_record(&mut self, record: &[u8]) -> Result<()> {
+        let mut line = record.to_vec();
+        line.push(b'\n');
+
+        // The file is not opened in append mode so we have to seek to the end ourselves
+        let journal = self.open_journal(None)?;
+
+        journal.seek(SeekFrom::End(0))?;
+        journal.write_all(&line)?;
+        journal.sync_data()?;
+
+        return Ok(());
+    }
+
+    fn clear_records(&mut self) -> Result<()> {
+        if self.journal.is_none() && !journal::path(&self.path).exists() {
+            return Ok(());
+        }
+
+        let journal = self.open_journal(None)?;
+
+        journal.set_len(0)?;
+        journal.sync_all()?;
+
+        return Ok(());
+    }
+}
+
+/// The path of the temporary file that [FileStorage::write_snapshot] writes before renaming it over the database
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    return PathBuf::from(tmp);
+}
+
+/// Sync the directory that holds the database so the rename in [FileStorage::write_snapshot] is durable
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+
+    return File::open(dir)?.sync_all();
+}
+
+/// Directories cannot be opened for syncing on this platform
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    return Ok(());
+}
+
+#[derive(Debug, Default)]
+struct Memory {
+    snapshot: Option<Vec<u8>>,
+    records: Vec<Vec<u8>>
+}
+
+/// A storage that keeps everything in memory, for tests that should not touch the disk.
+/// Clones share the same data, so a clone that is given to a new [Database](crate::Database)
+/// sees everything the first one saved like a file that is opened again
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// let storage = MemoryStorage::new();
+///
+/// let mut db = Database::with_storage(storage.clone());
+/// db.load().unwrap();
+/// db.insert("key", "value").unwrap();
+/// drop(db);
+///
+/// let mut reopened = Database::with_storage(storage);
+/// reopened.load().unwrap();
+///
+/// assert_eq!(reopened.get::<String>("key").unwrap(), "value");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage {
+    memory: Arc<Mutex<Memory>>
+}
+
+impl MemoryStorage {
+    /// Create an empty storage
+    pub fn new() -> MemoryStorage {
+        return MemoryStorage::default();
+    }
+}
+
+impl Storage for MemoryStorage {
+    /// There is nobody else to keep away
+    fn lock(&mut self, _shared: bool, _timeout: Option<Duration>) -> Result<()> {
+        return Ok(());
+    }
+
+    fn read_snapshot(&mut self) -> Result<Option<Vec<u8>>> {
+        return Ok(self.memory.lock().unwrap().snapshot.clone());
+    }
+
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> Result<()> {
+        self.memory.lock().unwrap().snapshot = Some(snapshot.to_vec());
+
+        return Ok(());
+    }
+
+    fn read_records(&mut self) -> Result<Vec<Vec<u8>>> {
+        return Ok(self.memory.lock().unwrap().records.clone());
+    }
+
+    fn append_record(&mut self, record: &[u8]) -> Result<()> {
+        self.memory.lock().unwrap().records.push(record.to_vec());
+
+        return Ok(());
+    }
+
+    fn clear_records(&mut self) -> Result<()> {
+        self.memory.lock().unwrap().records.clear();
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,628 @@
+//! Named tables inside of one database file
+//!
+//! The main tree of the file is the default table, so a database file without any
+//! named tables is just a flat json object like it always was. Named tables live in
+//! the `$tables` key of the main tree, which the default table hides together with
+//! the `$meta` key that holds things like the next document id of every table.
+
+use std::ops::RangeBounds;
+use std::sync::mpsc::Receiver;
+use std::time::{ Duration, SystemTime };
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::iter::{ self, Iter, Keys, Page, Values };
+use crate::journal::Record;
+use crate::doc::{ self, DocId, META };
+use crate::index;
+use crate::query::{ self, Filter };
+use crate::update::{ Update, Updated };
+use crate::schema::{ self, Schema };
+use crate::ttl;
+use crate::{ pointer, inner_path, Database, Error, Event, Query, Result, Transaction, Tree, Value };
+
+/// The key of the main tree that holds the named tables
+pub(crate) const TABLES: &str = "$tables";
+
+/// Check if a key of the main tree is used by dino itself and hidden from the default table
+pub(crate) fn is_reserved(key: &str) -> bool {
+    return key == TABLES || key == doc::META;
+}
+
+/// Make sure a path of the default table does not reach into the keys that dino uses itself
+pub(crate) fn check_default(path: &[String]) -> Result<()> {
+    return match path.first() {
+        Some(key) if is_reserved(key) => Err(Error::InvalidPath(pointer::format(path))),
+        _ => Ok(())
+    }
+}
+
+/// What a change at a path of the main tree touched in the tables
+pub(crate) enum Touched {
+    /// One entry of a table
+    Entry(Option<String>, String),
+
+    /// A whole table
+    Table(String),
+
+    /// Every named table
+    Tables,
+
+    /// The metadata of the tables, like the definitions of the indexes and the schemas
+    Meta
+}
+
+pub(crate) fn touched(path: &[String]) -> Touched {
+    return match path {
+        [first, ..] if first == META => Touched::Meta,
+        [first] if first == TABLES => Touched::Tables,
+        [first, name] if first == TABLES => Touched::Table(name.clone()),
+        [first, name, key, ..] if first == TABLES => Touched::Entry(Some(name.clone()), key.clone()),
+        [key, ..] => Touched::Entry(None, key.clone()),
+        [] => Touched::Tables
+    }
+}
+
+/// The entry at `key` of the table called `name` in the main tree. The default table is [None]
+pub(crate) fn entry<'r>(root: &'r serde_json::Value, name: Option<&str>, key: &str) -> Option<&'r serde_json::Value> {
+    return match name {
+        Some(name) => root.get(TABLES).and_then(|tables| tables.get(name)).and_then(|table| table.get(key)),
+        None if is_reserved(key) => None,
+        None => root.get(key)
+    }
+}
+
+/// The entries of the table called `name` in the main tree. The default table is [None]
+pub(crate) fn entries<'r>(root: &'r serde_json::Value, name: Option<&str>) -> impl Iterator<Item = (&'r String, &'r serde_json::Value)> + 'r {
+    let map = match name {
+        Some(name) => root.get(TABLES).and_then(|tables| tables.get(name)),
+        None => Some(root)
+    };
+
+    let default = name.is_none();
+
+    return map.and_then(|map| map.as_object())
+        .into_iter()
+        .flat_map(|map| map.iter())
+        .filter(move |(key, _)| !(default && is_reserved(key)));
+}
+
+/// A handle to a table of a [Database] that is returned by [Database::table]
+/// It has the same api as the [Database] but everything happens inside of the table.
+/// A table is created by the first insert into it
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # let db = Database::in_memory();
+/// let orders = db.table("orders");
+///
+/// orders.insert("order-1", &42).unwrap();
+///
+/// // The key only exists in the `orders` table
+/// assert!(orders.contains_key("order-1"));
+/// assert!(!db.contains_key("order-1"));
+/// ```
+pub struct Table<'a> {
+    db: &'a Database,
+
+    /// The name of the table or [None] for the default table
+    name: Option<String>
+}
+
+impl<'a> Table<'a> {
+    pub(crate) fn new(db: &'a Database, name: Option<&str>) -> Table<'a> {
+        return Table {
+            db,
+            name: name.map(String::from)
+        }
+    }
+
+    /// The name of the table or [None] for the default table
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    /// The path in the main tree of a path in the table
+    pub(crate) fn path(&self, tokens: Vec<String>) -> Result<Vec<String>> {
+        return match &self.name {
+            Some(name) => Ok([TABLES.to_string(), name.clone()].iter().cloned().chain(tokens).collect()),
+
+            None => {
+                check_default(&tokens)?;
+
+                Ok(tokens)
+            }
+        }
+    }
+
+    /// The entries of the table in the main tree
+    /// Keys that have expired are left out
+    pub(crate) fn entries<'r>(&self, root: &'r serde_json::Value) -> impl Iterator<Item = (&'r String, &'r serde_json::Value)> + 'r {
+        let expires = ttl::expires(root, self.name());
+        let now = self.now();
+
+        return entries(root, self.name()).filter(move |(key, _)| !ttl::is_expired(expires, key, now));
+    }
+
+    /// The value at a path in the table. Keys that have expired are left out
+    fn lookup<'r>(&self, root: &'r serde_json::Value, tokens: Vec<String>) -> Option<&'r serde_json::Value> {
+        if tokens.first().is_some_and(|key| self.expired(root, key)) {
+            return None;
+        }
+
+        return self.path(tokens).ok().and_then(|path| pointer::get(root, &path));
+    }
+
+    /// Look a key up with `f`. A key that has expired is left out but stays until it is purged,
+    /// so a read never has to write. See [Database::purge_expired](crate::Database::purge_expired)
+    fn lookup_key<T, F: FnOnce(Option<&serde_json::Value>) -> T>(&self, key: &str, f: F) -> Result<T> {
+        return self.db.read(|root| f(self.lookup(root, vec![key.to_string()])));
+    }
+
+    /// The entry at `key` in the table unless it has expired by the time `now`
+    fn live<'r>(&self, root: &'r serde_json::Value, key: &str, now: u64) -> Option<&'r serde_json::Value> {
+        if ttl::is_expired(ttl::expires(root, self.name()), key, now) {
+            return None;
+        }
+
+        return entry(root, self.name(), key);
+    }
+
+    /// The current time of the clock of the database in milliseconds
+    fn now(&self) -> u64 {
+        return ttl::millis(self.db.clock.now());
+    }
+
+    /// Check if a key of the table has expired
+    fn expired(&self, root: &serde_json::Value, key: &str) -> bool {
+        return ttl::is_expired(ttl::expires(root, self.name()), key, self.now());
+    }
+
+    /// The value at a path in the table. The empty path is the whole table
+    fn value_at(&self, root: &serde_json::Value, tokens: Vec<String>) -> Option<serde_json::Value> {
+        if tokens.is_empty() {
+            return Some(serde_json::Value::Object(self.snapshot_of(root)));
+        }
+
+        return self.lookup(root, tokens).cloned();
+    }
+
+    /// A copy of the entries of the table
+    fn snapshot_of(&self, root: &serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        return self.entries(root).map(|(key, val)| (key.clone(), val.clone())).collect();
+    }
+
+    /// A copy of the table. A database that is not loaded yet has no items
+    fn snapshot(&self) -> serde_json::Map<String, serde_json::Value> {
+        return self.db.read(|root| self.snapshot_of(root)).unwrap_or_default();
+    }
+
+    /// Copies of the entries of the table for which `keep` returns true
+    pub(crate) fn filter<F: Fn(&serde_json::Value) -> bool>(&self, keep: F) -> Result<Vec<(String, serde_json::Value)>> {
+        return self.db.read(|root| {
+            return self.entries(root)
+                .filter(|(_, value)| keep(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+        });
+    }
+
+    /// Index a field of the entries of the table like `email` or `address/city` so [Table::find_by]
+    /// does not have to look at every entry. The index is kept up to date with every change and built again on load
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// let users = db.table("users");
+    ///
+    /// users.create_unique_index("email").unwrap();
+    /// users.insert("alice", &serde_json::json!({ "email": "a@b.c" })).unwrap();
+    ///
+    /// assert_eq!(users.find_by("email", "a@b.c").unwrap()[0].0, "alice");
+    ///
+    /// // The email is taken already
+    /// assert!(users.insert("bob", &serde_json::json!({ "email": "a@b.c" })).is_err());
+    /// ```
+    pub fn create_index(&self, field: &str) -> Result<()> {
+        return self.define_index(field, false);
+    }
+
+    /// Index a field that no two entries may have the same value in.
+    /// Creating it fails with [Error::Duplicate] when two entries share a value already,
+    /// and so does every change that would make them share one later
+    pub fn create_unique_index(&self, field: &str) -> Result<()> {
+        return self.define_index(field, true);
+    }
+
+    fn define_index(&self, field: &str, unique: bool) -> Result<()> {
+        pointer::parse(field)?;
+
+        let mut path = index::definitions_path(self.name());
+        path.push(field.to_string());
+
+        return self.db.change(Record::Set { path, value: serde_json::json!({ "unique": unique }) });
+    }
+
+    /// Remove the index of a field. Removing an index that does not exist does nothing
+    pub fn drop_index(&self, field: &str) -> Result<()> {
+        let mut path = index::definitions_path(self.name());
+        path.push(field.to_string());
+
+        return self.db.change(Record::Remove { path });
+    }
+
+    /// Return the fields of the table that have an index sorted by name
+    pub fn indexes(&self) -> Vec<String> {
+        return self.db.read_indexed(|_, indexes| indexes.fields(self.name())).unwrap_or_default();
+    }
+
+    /// Return the `(key, value)` pairs of the entries with `value` in `field`, sorted by key.
+    /// This uses the index of the field when there is one and looks at every entry when there is not
+    pub fn find_by<T: Serialize + ?Sized>(&self, field: &str, value: &T) -> Result<Vec<(String, Value)>> {
+        let value = serde_json::to_value(value)?;
+        let path = pointer::parse(field)?;
+
+        return self.db.read_indexed(|root, indexes| {
+            return match indexes.get(self.name(), field) {
+                Some(index) => index.get(&value)
+                    .filter_map(|key| self.lookup(root, vec![key.clone()]).map(|entry| (key.clone(), Value::from(entry.clone()))))
+                    .collect(),
+
+                None => {
+                    let mut found: Vec<(String, Value)> = self.entries(root)
+                        .filter(|(_, entry)| pointer::get(entry, &path).is_some_and(|field| query::equal(field, &value)))
+                        .map(|(key, entry)| (key.clone(), Value::from(entry.clone())))
+                        .collect();
+
+                    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                    found
+                }
+            }
+        });
+    }
+
+    /// Check every entry of the table against `schema` from now on. A change that would make an entry
+    /// not match fails with [Error::Invalid] before anything is saved. Setting a schema that the entries
+    /// of the table do not match fails the same way. The schema is saved with the database
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// let users = db.table("users");
+    ///
+    /// users.set_schema(Schema::fields(&[("name", "string"), ("age", "integer?")]).unwrap()).unwrap();
+    ///
+    /// users.insert("alice", &serde_json::json!({ "name": "Alice", "age": 30 })).unwrap();
+    ///
+    /// // The typo in `nmae` is caught
+    /// let error = users.insert("bob", &serde_json::json!({ "nmae": "Bob" })).unwrap_err();
+    /// assert!(matches!(error, Error::Invalid { .. }));
+    /// ```
+    pub fn set_schema(&self, schema: Schema) -> Result<()> {
+        return self.db.change(Record::Set { path: schema::schema_path(self.name()), value: schema.schema });
+    }
+
+    /// Stop checking the entries of the table against a schema
+    pub fn remove_schema(&self) -> Result<()> {
+        return self.db.change(Record::Remove { path: schema::schema_path(self.name()) });
+    }
+
+    /// Return the schema of the table if it has one
+    pub fn schema(&self) -> Option<Schema> {
+        return self.db.read(|root| pointer::get(root, &schema::schema_path(self.name())).cloned())
+            .ok()
+            .flatten()
+            .map(|schema| Schema { schema });
+    }
+
+    /// Start a [Query] over the entries of the table
+    pub fn query(self) -> Query<'a> {
+        return Query::new(self);
+    }
+
+    /// Insert a key and a value in the table
+    /// The value can be anything that implements [Serialize], like your own structs and enums
+    pub fn insert<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        return self.db.change(Record::Set { path: self.path(vec![key.to_string()])?, value: serde_json::to_value(value)? });
+    }
+
+    /// Insert a key with a subtree in the table
+    pub fn insert_tree(&self, key: &str, value: Tree) -> Result<()> {
+        return self.db.change(Record::Set { path: self.path(vec![key.to_string()])?, value: value.children.unwrap() });
+    }
+
+    /// Insert a key and a value in the table that expires after `ttl`.
+    /// An expired key is hidden from every read and removed later. Inserting the key again without a ttl makes it stay
+    pub fn insert_with_ttl<T: Serialize + ?Sized>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        let at = ttl::millis(self.db.clock.now() + ttl);
+
+        return self.db.change_all(&[
+            Record::Set { path: self.path(vec![key.to_string()])?, value: serde_json::to_value(value)? },
+            Record::Set { path: ttl::expiry_path(self.name(), key), value: serde_json::json!(at) }
+        ]);
+    }
+
+    /// Let a key of the table expire at `at`. Returns [Error::NotFound] when there is no such key
+    pub fn expire(&self, key: &str, at: SystemTime) -> Result<()> {
+        self.path(vec![key.to_string()])?;
+
+        return self.db.change_with(|root| {
+            if self.lookup(root, vec![key.to_string()]).is_none() {
+                return Err(Error::NotFound(key.to_string()));
+            }
+
+            return Ok(vec![Record::Set { path: ttl::expiry_path(self.name(), key), value: serde_json::json!(ttl::millis(at)) }]);
+        });
+    }
+
+    /// Return how long a key has left before it expires or [None] when it does not expire or does not exist
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        let now = self.now();
+
+        return self.db.read(|root| {
+            // Keys that do not exist or have expired do not have a ttl
+            self.lookup(root, vec![key.to_string()])?;
+
+            return ttl::expires(root, self.name())
+                .and_then(|expires| expires.get(key))
+                .and_then(|at| at.as_u64())
+                .map(|at| Duration::from_millis(at.saturating_sub(now)));
+        }).ok().flatten();
+    }
+
+    /// Return a channel that gets an [Event] for every change to a key of the table that starts with `prefix`.
+    /// The events are sent after the change is saved. The subscription ends when the receiver is dropped
+    pub fn subscribe(&self, prefix: &str) -> Receiver<Event> {
+        return self.db.watchers.subscribe(self.name(), prefix);
+    }
+
+    /// Remove a key in the table with its value
+    pub fn remove(&self, key: &str) -> Result<()> {
+        return self.db.change(Record::Remove { path: self.path(vec![key.to_string()])? });
+    }
+
+    /// Set the value at a path like `users/alice/age` in the table
+    /// Trees on the way that do not exist yet are created. See [Database::get_path] for the path syntax
+    pub fn set_path<T: Serialize + ?Sized>(&self, path: &str, value: &T) -> Result<()> {
+        return self.db.change(Record::Set { path: self.path(inner_path(path)?)?, value: serde_json::to_value(value)? });
+    }
+
+    /// Remove the value at a path like `users/alice/age` in the table
+    pub fn remove_path(&self, path: &str) -> Result<()> {
+        return self.db.change(Record::Remove { path: self.path(inner_path(path)?)? });
+    }
+
+    /// Insert a document under the next id of the table and return the id.
+    /// Ids count up from 1 and are never handed out twice, even when the document with the highest id was removed.
+    /// The document can be a [Tree] or anything else that implements [Serialize]
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # let db = Database::in_memory();
+    /// let users = db.table("users");
+    ///
+    /// let mut alice = Tree::new();
+    /// alice.insert("name", "Alice").unwrap();
+    ///
+    /// let id = users.insert_doc(&alice).unwrap();
+    ///
+    /// assert_eq!(users.find_doc(id).unwrap().to_tree().unwrap().get::<String>("name").unwrap(), "Alice");
+    /// ```
+    pub fn insert_doc<T: Serialize + ?Sized>(&self, doc: &T) -> Result<DocId> {
+        let value = serde_json::to_value(doc)?;
+
+        return self.db.transaction(|tx| self.add_doc(tx, value));
+    }
+
+    /// Insert a document under the next id of the table inside of a transaction
+    fn add_doc(&self, tx: &mut Transaction, value: serde_json::Value) -> Result<DocId> {
+        let counter = doc::counter_path(self.name());
+        let mut id = DocId(pointer::get(tx.root(), &counter).and_then(|next| next.as_u64()).unwrap_or(1));
+
+        // Skip the ids that were taken by keys that were inserted by hand
+        while self.lookup(tx.root(), vec![id.key()]).is_some() {
+            id.0 += 1;
+        }
+
+        tx.change(Record::Set { path: self.path(vec![id.key()])?, value })?;
+        tx.change(Record::Set { path: counter, value: serde_json::json!(id.0 + 1) })?;
+
+        return Ok(id);
+    }
+
+    /// Change every entry of the table that matches `filter` with the mongo style operators in `update`.
+    /// The filter looks like `{ "name": "alice", "age": { "$gt": 30 } }` with the operators of [Query].
+    /// The update operators are `$set`, `$unset`, `$inc`, `$push`, `$pull`, `$addToSet`, `$rename`, `$min` and `$max`.
+    /// All of the entries are changed in one go or none of them are when one of the changes fails
+    /// # Example
+    /// ```rust
+    /// # use dino::*;
+    /// # use serde_json::json;
+    /// # let db = Database::in_memory();
+    /// # db.insert("alice", &json!({ "name": "alice", "visits": 1 })).unwrap();
+    /// let updated = db.update(
+    ///     &json!({ "name": "alice" }),
+    ///     &json!({ "$inc": { "visits": 1 }, "$set": { "address/city": "Oslo" } })
+    /// ).unwrap();
+    ///
+    /// assert_eq!(updated.modified, 1);
+    /// assert_eq!(db.get_path::<u32>("alice/visits").unwrap(), 2);
+    /// ```
+    pub fn update(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.update_with(filter, update, false);
+    }
+
+    /// Like [Table::update] but when nothing matches a new document is inserted with [Table::insert_doc].
+    /// It starts out with the fields that the filter wants to be equal to a value and then the update is applied to it
+    pub fn upsert(&self, filter: &serde_json::Value, update: &serde_json::Value) -> Result<Updated> {
+        return self.update_with(filter, update, true);
+    }
+
+    fn update_with(&self, filter: &serde_json::Value, update: &serde_json::Value, upsert: bool) -> Result<Updated> {
+        let filter = Filter::parse(filter)?;
+        let update = Update::parse(update)?;
+
+        return self.db.transaction(|tx| {
+            let matched: Vec<(String, serde_json::Value)> = self.entries(tx.root())
+                .filter(|(_, value)| filter.matches(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+
+            let mut updated = Updated { matched: matched.len(), modified: 0, upserted: None };
+
+            for (key, value) in matched {
+                let mut changed = value.clone();
+                update.apply(&mut changed)?;
+
+                if changed != value {
+                    tx.change(Record::Set { path: self.path(vec![key])?, value: changed })?;
+                    updated.modified += 1;
+                }
+            }
+
+            if upsert && updated.matched == 0 {
+                let mut doc = serde_json::json!({});
+
+                for (path, value) in filter.equalities() {
+                    pointer::set(&mut doc, path, value.clone())?;
+                }
+
+                update.apply(&mut doc)?;
+                updated.upserted = Some(self.add_doc(tx, doc)?);
+            }
+
+            return Ok(updated);
+        });
+    }
+
+    /// Get the document with the id `id` as any type that implements [DeserializeOwned]
+    pub fn get_doc<T: DeserializeOwned>(&self, id: DocId) -> Result<T> {
+        return self.get(&id.key());
+    }
+
+    /// Find the document with the id `id`
+    pub fn find_doc(&self, id: DocId) -> Result<Value> {
+        return self.find(&id.key());
+    }
+
+    /// Replace the document with the id `id`. Returns [Error::NotFound] when there is no such document
+    pub fn update_doc<T: Serialize + ?Sized>(&self, id: DocId, doc: &T) -> Result<()> {
+        let value = serde_json::to_value(doc)?;
+
+        return self.db.transaction(|tx| {
+            if self.lookup(tx.root(), vec![id.key()]).is_none() {
+                return Err(Error::NotFound(id.key()));
+            }
+
+            return tx.change(Record::Set { path: self.path(vec![id.key()])?, value });
+        });
+    }
+
+    /// Remove the document with the id `id`. Its id is not handed out again
+    pub fn remove_doc(&self, id: DocId) -> Result<()> {
+        return self.remove(&id.key());
+    }
+
+    /// Get a value in the table as any type that implements [DeserializeOwned]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        return self.lookup_key(key, |val| {
+            return match val {
+                Some(val) => Ok(T::deserialize(val)?),
+                None => Err(Error::NotFound(key.to_string()))
+            }
+        })?;
+    }
+
+    /// Find a value in the table
+    pub fn find(&self, key: &str) -> Result<Value> {
+        return self.lookup_key(key, |val| {
+            return match val {
+                Some(val) => Ok(Value::from(val.clone())),
+                None => Err(Error::NotFound(key.to_string()))
+            }
+        })?;
+    }
+
+    /// Get the value at a path like `users/alice/age` in the table as any type that implements [DeserializeOwned]
+    pub fn get_path<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let tokens = pointer::parse(path)?;
+
+        return match self.db.read(|root| self.value_at(root, tokens))? {
+            Some(val) => Ok(T::deserialize(val)?),
+            None => Err(Error::NotFound(path.to_string()))
+        }
+    }
+
+    /// Find the value at a path like `users/alice/age` in the table
+    pub fn find_path(&self, path: &str) -> Result<Value> {
+        let tokens = pointer::parse(path)?;
+
+        return match self.db.read(|root| self.value_at(root, tokens))? {
+            Some(val) => Ok(Value::from(val)),
+            None => Err(Error::NotFound(path.to_string()))
+        }
+    }
+
+    /// Check if the key exists in the table
+    /// A database that is not loaded yet does not contain any keys
+    pub fn contains_key(&self, key: &str) -> bool {
+        return self.lookup_key(key, |val| val.is_some()).unwrap_or(false);
+    }
+
+    /// Return the length of items that are in the table
+    /// A database that is not loaded yet has no items
+    pub fn len(&self) -> usize {
+        return self.db.read(|root| self.entries(root).count()).unwrap_or(0);
+    }
+
+    /// Check if the table has no items
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Return an iterator over the `(key, value)` pairs of the table in key order
+    /// The iterator works on a snapshot so changes made while iterating do not show up in it
+    pub fn iter(&self) -> Iter {
+        return Iter::new(self.snapshot());
+    }
+
+    /// Return an iterator over the keys of the table in key order
+    pub fn keys(&self) -> Keys {
+        return Keys::new(self.snapshot());
+    }
+
+    /// Return an iterator over the values of the table in key order
+    pub fn values(&self) -> Values {
+        return Values::new(self.snapshot());
+    }
+
+    /// Return an iterator over the entries with a key that starts with `prefix`, sorted by key
+    pub fn scan_prefix(&self, prefix: &str) -> Iter {
+        return self.scan(&.., prefix);
+    }
+
+    /// Return an iterator over the entries with a key in `range` like `"a".."m"`, sorted by key
+    pub fn range<'r, R: RangeBounds<&'r str>>(&self, range: R) -> Iter {
+        return self.scan(&range, "");
+    }
+
+    /// Return up to `limit` entries with a key that starts with `prefix`, sorted by key.
+    /// Pass [Page::next] of a page as `after` to get the page that comes after it.
+    /// A `limit` of `0` returns [Error::InvalidQuery]
+    pub fn page(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Page> {
+        let now = self.now();
+
+        return self.db.read_indexed(|root, indexes| iter::page(indexes.keys(self.name()), prefix, after, limit, |key| self.live(root, key, now)))?;
+    }
+
+    /// The entries with a key in `range` that starts with `prefix`, sorted by key
+    fn scan<'r, R: RangeBounds<&'r str> + ?Sized>(&self, range: &R, prefix: &str) -> Iter {
+        let now = self.now();
+
+        return self.db.read_indexed(|root, indexes| {
+            return Iter::sorted(iter::scan(indexes.keys(self.name()), range, prefix).filter_map(|key| self.live(root, key, now).map(|val| (key, val))));
+        }).unwrap_or_else(|_| Iter::new(serde_json::Map::new()));
+    }
+}
@@ -0,0 +1,144 @@
+//! Changing many keys of a [Database](crate::Database) all or nothing
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::journal::Record;
+use crate::{ pointer, table, ttl, inner_path, Error, Result, Tree, Value };
+
+/// The handle that [Database::transaction](crate::Database::transaction) gives to its closure.
+/// Changes are made to a copy of the main tree that replaces the real one when the transaction is saved.
+/// Reads see the changes that were made earlier in the same transaction
+pub struct Transaction {
+    /// The copy of the main tree that the changes are made to
+    root: serde_json::Value,
+
+    /// The changes in the order they were made, ready for the journal
+    records: Vec<Record>,
+
+    /// The time of the transaction in milliseconds, to hide the keys that have expired
+    now: u64
+}
+
+impl Transaction {
+    pub(crate) fn new(root: serde_json::Value, now: u64) -> Transaction {
+        return Transaction {
+            root,
+            records: Vec::new(),
+            now
+        }
+    }
+
+    /// The changed main tree and the changes that lead to it
+    pub(crate) fn finish(self) -> (serde_json::Value, Vec<Record>) {
+        return (self.root, self.records);
+    }
+
+    /// The copy of the main tree with the changes made so far
+    pub(crate) fn root(&self) -> &serde_json::Value {
+        return &self.root;
+    }
+
+    /// Apply a change to the copy of the main tree. The path is not checked against the reserved keys
+    pub(crate) fn change(&mut self, record: Record) -> Result<()> {
+        // A key that is replaced or removed as a whole does not expire anymore
+        if let Some(forget) = ttl::forget(&self.root, &record) {
+            forget.apply(&mut self.root)?;
+            self.records.push(forget);
+        }
+
+        record.apply(&mut self.root)?;
+        self.records.push(record.durable(&self.root));
+
+        return Ok(());
+    }
+
+    /// Apply a change to the default table
+    fn change_default(&mut self, record: Record) -> Result<()> {
+        table::check_default(record.path())?;
+
+        return self.change(record);
+    }
+
+    /// The value at `key` of the default table
+    fn lookup(&self, key: &str) -> Option<&serde_json::Value> {
+        return if self.hidden(key) { None } else { self.root.get(key) };
+    }
+
+    /// Check if a key of the main tree is reserved or has expired
+    fn hidden(&self, key: &str) -> bool {
+        return table::is_reserved(key) || ttl::is_expired(ttl::expires(&self.root, None), key, self.now);
+    }
+
+    /// Insert a key and a value in the database
+    pub fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        return self.change_default(Record::set(key, serde_json::to_value(value)?));
+    }
+
+    /// Insert a key with a subtree in the database
+    pub fn insert_tree(&mut self, key: &str, value: Tree) -> Result<()> {
+        return self.change_default(Record::set(key, value.children.unwrap()));
+    }
+
+    /// Remove a key in the database with its value
+    pub fn remove(&mut self, key: &str) -> Result<()> {
+        return self.change_default(Record::remove(key));
+    }
+
+    /// Set the value at a path like `users/alice/age` in the database
+    pub fn set_path<T: Serialize + ?Sized>(&mut self, path: &str, value: &T) -> Result<()> {
+        return self.change_default(Record::Set { path: inner_path(path)?, value: serde_json::to_value(value)? });
+    }
+
+    /// Remove the value at a path like `users/alice/age` in the database
+    pub fn remove_path(&mut self, path: &str) -> Result<()> {
+        return self.change_default(Record::Remove { path: inner_path(path)? });
+    }
+
+    /// Get a value as any type that implements [DeserializeOwned]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        return match self.lookup(key) {
+            Some(val) => Ok(T::deserialize(val)?),
+            None => Err(Error::NotFound(key.to_string()))
+        }
+    }
+
+    /// Find a value in the database
+    pub fn find(&self, key: &str) -> Result<Value> {
+        return match self.lookup(key) {
+            Some(val) => Ok(Value::from(val.clone())),
+            None => Err(Error::NotFound(key.to_string()))
+        }
+    }
+
+    /// Get the value at a path like `users/alice/age` as any type that implements [DeserializeOwned]
+    pub fn get_path<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let tokens = pointer::parse(path)?;
+
+        table::check_default(&tokens)?;
+
+        if tokens.first().is_some_and(|key| self.hidden(key)) {
+            return Err(Error::NotFound(path.to_string()));
+        }
+
+        return match pointer::get(&self.root, &tokens) {
+            Some(val) => Ok(T::deserialize(val)?),
+            None => Err(Error::NotFound(path.to_string()))
+        }
+    }
+
+    /// Check if the key exists in the database
+    pub fn contains_key(&self, key: &str) -> bool {
+        return self.lookup(key).is_some();
+    }
+
+    /// Return the length of items that are in the main tree
+    pub fn len(&self) -> usize {
+        return self.root.as_object().map_or(0, |main| main.keys().filter(|key| !self.hidden(key)).count());
+    }
+
+    /// Check if the main tree has no items
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
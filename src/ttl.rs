@@ -0,0 +1,215 @@
+//! Keys that expire after some time
+//!
+//! The time a key expires at is kept in the `$meta` key of the main tree as milliseconds since
+//! the unix epoch so it survives a reload. Expired keys are hidden from every read right away
+//! and removed in bulk by [Database::purge_expired](crate::Database::purge_expired) or a [Sweeper]
+//! that calls it in the background, so reads never write.
+
+use std::sync::{ Arc, Condvar, Mutex, Weak };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use crate::doc;
+use crate::journal::Record;
+use crate::table::{ self, touched, Touched, TABLES };
+use crate::{ pointer, Database };
+
+/// Where a [Database] gets the current time from. Set it with [Database::set_clock]
+pub trait Clock: Send + Sync {
+    /// The current time
+    fn now(&self) -> SystemTime;
+}
+
+/// The clock of the system. This is the clock a [Database] uses unless another one is set
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        return SystemTime::now();
+    }
+}
+
+/// A clock that only moves when it is told to, for tests
+/// # Example
+/// ```rust
+/// # use dino::*;
+/// # use std::sync::Arc;
+/// # use std::time::{ Duration, UNIX_EPOCH };
+/// let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+///
+/// let mut db = Database::in_memory();
+/// db.set_clock(clock.clone());
+///
+/// db.insert_with_ttl("session", "token", Duration::from_secs(60)).unwrap();
+/// clock.advance(Duration::from_secs(61));
+///
+/// assert!(!db.contains_key("session"));
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>
+}
+
+impl ManualClock {
+    /// Create a clock that stands at `now`
+    pub fn new(now: SystemTime) -> ManualClock {
+        return ManualClock {
+            now: Mutex::new(now)
+        }
+    }
+
+    /// Move the clock to `now`
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        return *self.now.lock().unwrap();
+    }
+}
+
+/// A time as milliseconds since the unix epoch. Times before the epoch are the epoch
+pub(crate) fn millis(time: SystemTime) -> u64 {
+    return time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64);
+}
+
+/// The path in the main tree of the expiry times of a table. The default table is [None]
+pub(crate) fn expires_path(table: Option<&str>) -> Vec<String> {
+    let mut path = doc::meta_path(table);
+    path.push("expires".to_string());
+
+    return path;
+}
+
+/// The path in the main tree of the expiry time of a key
+pub(crate) fn expiry_path(table: Option<&str>, key: &str) -> Vec<String> {
+    let mut path = expires_path(table);
+    path.push(key.to_string());
+
+    return path;
+}
+
+/// The expiry times of the keys of a table
+pub(crate) fn expires<'r>(root: &'r serde_json::Value, table: Option<&str>) -> Option<&'r serde_json::Map<String, serde_json::Value>> {
+    return pointer::get(root, &expires_path(table)).and_then(|expires| expires.as_object());
+}
+
+/// Check if a key expired by the time `now` in milliseconds
+pub(crate) fn is_expired(expires: Option<&serde_json::Map<String, serde_json::Value>>, key: &str, now: u64) -> bool {
+    return expires.and_then(|expires| expires.get(key)).and_then(|at| at.as_u64()).is_some_and(|at| at <= now);
+}
+
+/// A record that forgets the expiry time of a key that `record` replaced or removed as a whole.
+/// A key that is inserted again without a ttl does not expire anymore
+pub(crate) fn forget(root: &serde_json::Value, record: &Record) -> Option<Record> {
+    let depth = if record.path().first().is_some_and(|first| first == TABLES) { 3 } else { 1 };
+
+    if record.path().len() != depth {
+        return None;
+    }
+
+    return match touched(record.path()) {
+        Touched::Entry(table, key) => {
+            let path = expiry_path(table.as_deref(), &key);
+
+            pointer::get(root, &path).map(|_| Record::Remove { path })
+        },
+
+        _ => None
+    }
+}
+
+/// The records that remove every key that expired by the time `now` in milliseconds, with their expiry times
+pub(crate) fn purge(root: &serde_json::Value, now: u64) -> Vec<Record> {
+    let mut tables = vec![None];
+
+    if let Some(named) = root.get(TABLES).and_then(|tables| tables.as_object()) {
+        tables.extend(named.keys().map(|name| Some(name.as_str())));
+    }
+
+    let mut records = Vec::new();
+
+    for table in tables {
+        for (key, at) in expires(root, table).into_iter().flatten() {
+            if at.as_u64().is_some_and(|at| at <= now) {
+                records.extend(purge_key(root, table, key));
+            }
+        }
+    }
+
+    return records;
+}
+
+/// The records that remove a key and its expiry time
+fn purge_key(root: &serde_json::Value, table: Option<&str>, key: &str) -> Vec<Record> {
+    if table::entry(root, table, key).is_none() {
+        return vec![Record::Remove { path: expiry_path(table, key) }];
+    }
+
+    let mut path = match table {
+        Some(name) => vec![TABLES.to_string(), name.to_string()],
+        None => Vec::new()
+    };
+
+    path.push(key.to_string());
+
+    // Removing the key forgets its expiry time too
+    return vec![Record::Remove { path }];
+}
+
+/// A thread that removes the expired keys of a [Database] every now and then.
+/// It is started with [Database::start_sweeper] and stops when it is dropped or the database goes away
+pub struct Sweeper {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Sweeper {
+    pub(crate) fn start(db: Weak<Database>, interval: Duration) -> Sweeper {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let (stopped, wake) = &*signal;
+            let mut stopped = stopped.lock().unwrap();
+
+            loop {
+                // Waiting only while not stopped catches a stop that came before the wait started
+                stopped = wake.wait_timeout_while(stopped, interval, |stopped| !*stopped).unwrap().0;
+
+                if *stopped {
+                    return;
+                }
+
+                match db.upgrade() {
+                    Some(db) => { let _ = db.purge_expired(); },
+                    None => return
+                }
+            }
+        });
+
+        return Sweeper {
+            stop,
+            thread: Some(thread)
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        *self.stop.0.lock().unwrap() = true;
+        self.stop.1.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
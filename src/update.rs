@@ -0,0 +1,177 @@
+//! Changing fields of the entries that match a filter with mongo style operators
+//!
+//! An update like `{ "$set": { "address/city": "Oslo" }, "$inc": { "visits": 1 } }` is
+//! applied to every entry that matches the filter of [Table::update](crate::Table::update)
+//! so small changes to nested trees do not need a read, a clone and an insert.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use crate::query::{ compare, equal, integer };
+use crate::{ pointer, DocId, Error, Result };
+
+/// What [Table::update](crate::Table::update) did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Updated {
+    /// The amount of entries that matched the filter
+    pub matched: usize,
+
+    /// The amount of entries that were changed by the update
+    pub modified: usize,
+
+    /// The id of the document that was inserted because nothing matched in an upsert
+    pub upserted: Option<DocId>
+}
+
+/// One operator of an update with the field it works on
+enum Op {
+    Set(serde_json::Value),
+    Unset,
+    Inc(serde_json::Number),
+    Push(serde_json::Value),
+    Pull(serde_json::Value),
+    AddToSet(serde_json::Value),
+    Rename(Vec<String>),
+    Min(serde_json::Value),
+    Max(serde_json::Value)
+}
+
+/// A parsed update that can be applied to many entries
+pub(crate) struct Update {
+    ops: Vec<(Vec<String>, Op)>
+}
+
+impl Update {
+    /// Parse an update like `{ "$set": { "name": "alice" }, "$unset": { "age": "" } }`
+    pub(crate) fn parse(update: &serde_json::Value) -> Result<Update> {
+        let operators = update.as_object().ok_or_else(|| Error::mismatch("tree", update))?;
+        let mut ops = Vec::new();
+
+        for (operator, fields) in operators {
+            let fields = fields.as_object().ok_or_else(|| Error::mismatch("tree", fields))?;
+
+            for (field, value) in fields {
+                let path = pointer::parse(field)?;
+
+                if path.is_empty() {
+                    return Err(Error::InvalidPath(field.clone()));
+                }
+
+                let op = match operator.as_str() {
+                    "$set" => Op::Set(value.clone()),
+                    "$unset" => Op::Unset,
+                    "$inc" => Op::Inc(value.as_number().ok_or_else(|| Error::mismatch("number", value))?.clone()),
+                    "$push" => Op::Push(value.clone()),
+                    "$pull" => Op::Pull(value.clone()),
+                    "$addToSet" => Op::AddToSet(value.clone()),
+                    "$min" => Op::Min(value.clone()),
+                    "$max" => Op::Max(value.clone()),
+
+                    "$rename" => {
+                        let to = pointer::parse(value.as_str().ok_or_else(|| Error::mismatch("string", value))?)?;
+
+                        if to.is_empty() {
+                            return Err(Error::InvalidPath(String::new()));
+                        }
+
+                        Op::Rename(to)
+                    },
+
+                    _ => return Err(Error::InvalidQuery(format!("unknown update operator `{}`", operator)))
+                };
+
+                ops.push((path, op));
+            }
+        }
+
+        return Ok(Update { ops });
+    }
+
+    /// Apply the update to an entry
+    pub(crate) fn apply(&self, entry: &mut serde_json::Value) -> Result<()> {
+        for (path, op) in &self.ops {
+            let current = pointer::get(entry, path);
+
+            match op {
+                Op::Set(value) => {
+                    pointer::set(entry, path, value.clone())?;
+                },
+
+                Op::Unset => {
+                    pointer::remove(entry, path)?;
+                },
+
+                Op::Inc(by) => {
+                    let value = match current {
+                        None => serde_json::Value::Number(by.clone()),
+                        Some(serde_json::Value::Number(number)) => add(number, by).ok_or_else(|| Error::Overflow(pointer::format(path)))?,
+                        Some(value) => return Err(Error::mismatch("number", value))
+                    };
+
+                    pointer::set(entry, path, value)?;
+                },
+
+                Op::Push(value) | Op::AddToSet(value) => {
+                    let mut array = match current {
+                        None => Vec::new(),
+                        Some(serde_json::Value::Array(array)) => array.clone(),
+                        Some(value) => return Err(Error::mismatch("array", value))
+                    };
+
+                    if matches!(op, Op::Push(_)) || !array.iter().any(|item| equal(item, value)) {
+                        array.push(value.clone());
+                    }
+
+                    pointer::set(entry, path, serde_json::Value::Array(array))?;
+                },
+
+                Op::Pull(value) => {
+                    match current {
+                        None => {},
+
+                        Some(serde_json::Value::Array(array)) => {
+                            let array = array.iter().filter(|item| !equal(item, value)).cloned().collect();
+
+                            pointer::set(entry, path, serde_json::Value::Array(array))?;
+                        },
+
+                        Some(value) => return Err(Error::mismatch("array", value))
+                    }
+                },
+
+                Op::Rename(to) => {
+                    if let Some(value) = pointer::remove(entry, path)? {
+                        pointer::set(entry, to, value)?;
+                    }
+                },
+
+                Op::Min(value) | Op::Max(value) => {
+                    let wanted = if matches!(op, Op::Min(_)) { Ordering::Less } else { Ordering::Greater };
+
+                    if current.is_none_or(|current| compare(value, current) == Some(wanted)) {
+                        pointer::set(entry, path, value.clone())?;
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// Add two numbers. Two integers stay an integer, signed or up to [u64::MAX], and give [None] when
+/// the sum does not fit instead of losing precision as a float. A float on either side makes a float,
+/// which gives [None] too when it overflows to infinity
+fn add(a: &serde_json::Number, b: &serde_json::Number) -> Option<serde_json::Value> {
+    if let (Some(a), Some(b)) = (integer(a), integer(b)) {
+        let sum = a + b;
+
+        return i64::try_from(sum).map(serde_json::Value::from)
+            .or_else(|_| u64::try_from(sum).map(serde_json::Value::from))
+            .ok();
+    }
+
+    let sum = a.as_f64().unwrap_or(0.0) + b.as_f64().unwrap_or(0.0);
+
+    return sum.is_finite().then(|| serde_json::json!(sum));
+}
@@ -0,0 +1,269 @@
+//! Telling subscribers about the entries that changed
+//!
+//! The value of every entry that a change touches is taken before the change is applied and
+//! compared with its value after the change is saved. The entries that really changed become
+//! [Event]s that are sent to the channels of [Database::subscribe](crate::Database::subscribe)
+//! and to the callbacks of [Database::on_change](crate::Database::on_change).
+//! The events of one change are delivered together and in the order the changes were saved.
+
+use std::collections::{ BTreeMap, BTreeSet, VecDeque };
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::sync::{ Arc, Mutex, TryLockError };
+
+use crate::journal::Record;
+use crate::table::{ self, touched, Touched, TABLES };
+use crate::Value;
+
+/// What happened to an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The entry did not exist before
+    Insert,
+
+    /// The entry existed and got another value
+    Update,
+
+    /// The entry was removed
+    Remove
+}
+
+/// A change to one entry of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The table of the entry. The default table is [None]
+    pub table: Option<String>,
+
+    /// The key of the entry
+    pub key: String,
+
+    /// The value before the change or [None] when the entry was inserted
+    pub old: Option<Value>,
+
+    /// The value after the change or [None] when the entry was removed
+    pub new: Option<Value>,
+
+    /// What happened to the entry
+    pub kind: EventKind
+}
+
+/// The values of the entries that some records are going to change, taken before they are applied
+pub(crate) struct Before {
+    old: BTreeMap<(Option<String>, String), Option<serde_json::Value>>,
+
+    /// The tables that are replaced as a whole, so the entries they get afterwards are new too
+    whole: BTreeSet<Option<String>>,
+
+    /// Every named table is replaced, so tables that are only there afterwards are new too
+    all: bool
+}
+
+impl Before {
+    pub(crate) fn take(root: &serde_json::Value, records: &[Record]) -> Before {
+        let mut before = Before {
+            old: BTreeMap::new(),
+            whole: BTreeSet::new(),
+            all: false
+        };
+
+        for record in records {
+            match touched(record.path()) {
+                Touched::Entry(table, key) => {
+                    let old = table::entry(root, table.as_deref(), &key).cloned();
+
+                    before.old.entry((table, key)).or_insert(old);
+                },
+
+                Touched::Table(name) => before.table(root, Some(name)),
+
+                Touched::Tables => {
+                    // Replacing the main tree replaces the default table too
+                    if record.path().is_empty() {
+                        before.table(root, None);
+                    }
+
+                    let names: Vec<String> = root.get(TABLES).and_then(|tables| tables.as_object())
+                        .map_or_else(Vec::new, |tables| tables.keys().cloned().collect());
+
+                    for name in names {
+                        before.table(root, Some(name));
+                    }
+
+                    before.all = true;
+                },
+
+                Touched::Meta => {}
+            }
+        }
+
+        return before;
+    }
+
+    fn table(&mut self, root: &serde_json::Value, name: Option<String>) {
+        for (key, entry) in table::entries(root, name.as_deref()) {
+            self.old.entry((name.clone(), key.clone())).or_insert_with(|| Some(entry.clone()));
+        }
+
+        self.whole.insert(name);
+    }
+
+    /// The events for the entries that have another value in `root` now
+    pub(crate) fn events(mut self, root: &serde_json::Value) -> Vec<Event> {
+        if self.all {
+            if let Some(tables) = root.get(TABLES).and_then(|tables| tables.as_object()) {
+                self.whole.extend(tables.keys().cloned().map(Some));
+            }
+        }
+
+        for name in &self.whole {
+            for (key, _) in table::entries(root, name.as_deref()) {
+                self.old.entry((name.clone(), key.clone())).or_insert(None);
+            }
+        }
+
+        let mut events = Vec::new();
+
+        for ((table, key), old) in self.old {
+            let new = table::entry(root, table.as_deref(), &key);
+
+            let kind = match (&old, new) {
+                (None, None) => continue,
+                (Some(old), Some(new)) if old == new => continue,
+                (None, Some(_)) => EventKind::Insert,
+                (Some(_), Some(_)) => EventKind::Update,
+                (Some(_), None) => EventKind::Remove
+            };
+
+            events.push(Event {
+                table,
+                key,
+                old: old.map(Value::from),
+                new: new.cloned().map(Value::from),
+                kind
+            });
+        }
+
+        return events;
+    }
+}
+
+type Callback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// A channel that gets the events of the keys in a table that start with a prefix
+struct Subscriber {
+    table: Option<String>,
+    prefix: String,
+    sender: Sender<Event>
+}
+
+#[derive(Default)]
+struct State {
+    subscribers: Vec<Subscriber>,
+    callbacks: Vec<Callback>,
+
+    /// The events of the changes that are saved but not delivered yet, one list per change
+    queue: VecDeque<Vec<Event>>,
+
+    /// The events of the changes that wait for [Database::flush](crate::Database::flush) in manual flush mode
+    unflushed: Vec<Vec<Event>>
+}
+
+/// The subscribers and callbacks of a database
+#[derive(Default)]
+pub(crate) struct Watchers {
+    state: Mutex<State>,
+
+    /// Held by the thread that delivers the queue so the events go out one change after the other
+    delivering: Mutex<()>
+}
+
+impl Watchers {
+    pub(crate) fn subscribe(&self, table: Option<&str>, prefix: &str) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.state.lock().unwrap().subscribers.push(Subscriber {
+            table: table.map(String::from),
+            prefix: prefix.to_string(),
+            sender
+        });
+
+        return receiver;
+    }
+
+    pub(crate) fn on_change(&self, callback: Callback) {
+        self.state.lock().unwrap().callbacks.push(callback);
+    }
+
+    /// Check if anyone listens, so changes only collect events when they are needed
+    pub(crate) fn is_watched(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        return !state.subscribers.is_empty() || !state.callbacks.is_empty();
+    }
+
+    /// Queue the events of a saved change. Call this while the main tree is still locked so they keep the order of the changes
+    pub(crate) fn push(&self, events: Vec<Event>) {
+        if !events.is_empty() {
+            self.state.lock().unwrap().queue.push_back(events);
+        }
+    }
+
+    /// Keep the events of a change until it is flushed
+    pub(crate) fn hold(&self, events: Vec<Event>) {
+        if !events.is_empty() {
+            self.state.lock().unwrap().unflushed.push(events);
+        }
+    }
+
+    /// Queue the events of the changes that were flushed
+    pub(crate) fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        let unflushed = std::mem::take(&mut state.unflushed);
+
+        state.queue.extend(unflushed);
+    }
+
+    /// Deliver the queued events. Call this after the main tree is unlocked so callbacks can read the database.
+    /// When another thread is delivering already it delivers these too. That includes a change made inside of a callback
+    pub(crate) fn deliver(&self) {
+        loop {
+            let delivering = match self.delivering.try_lock() {
+                Ok(delivering) => delivering,
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => return
+            };
+
+            loop {
+                let (events, callbacks) = {
+                    let mut state = self.state.lock().unwrap();
+
+                    let events = match state.queue.pop_front() {
+                        Some(events) => events,
+                        None => break
+                    };
+
+                    // Subscribers whose receiver is gone are dropped
+                    state.subscribers.retain(|subscriber| {
+                        return events.iter()
+                            .filter(|event| event.table == subscriber.table && event.key.starts_with(&subscriber.prefix))
+                            .all(|event| subscriber.sender.send(event.clone()).is_ok());
+                    });
+
+                    (events, state.callbacks.clone())
+                };
+
+                for event in &events {
+                    for callback in &callbacks {
+                        callback(event);
+                    }
+                }
+            }
+
+            drop(delivering);
+
+            // Events that were queued while we let go of the lock would be left behind otherwise
+            if self.state.lock().unwrap().queue.is_empty() {
+                return;
+            }
+        }
+    }
+}